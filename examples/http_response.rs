@@ -1,7 +1,15 @@
 use http::{StatusCode, Uri};
-use maker_web::{HttpServer, Request, Response};
+use maker_web::{HttpServer, IntoResponse, Request, Response, StaticFiles};
+use once_cell::sync::Lazy;
 use tokio::net::TcpListener;
 
+// Every file of "examples_rs" under "/files/", with listings of its directories.
+static FILES: Lazy<StaticFiles> = Lazy::new(|| {
+    let mut files = StaticFiles::new("/files", "examples_rs");
+    files.set_listing(true);
+    files
+});
+
 #[tokio::main]
 async fn main() {
     HttpServer::launch(TcpListener::bind("127.0.0.1:3").await.unwrap(), work).await;
@@ -9,6 +17,10 @@ async fn main() {
 
 #[inline]
 async fn work(request: Request) -> Response {
+    if let Some(response) = FILES.serve(&request).await {
+        return response.into_response();
+    }
+
     let mut response = Response::new();
 
     // Sending files
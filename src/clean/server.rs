@@ -1,7 +1,127 @@
 use crate::*;
 
 /// Non-protocol server.
-pub struct CleanServer;
+#[derive(Clone)]
+pub struct CleanServer {
+    on_error: ErrorHook,
+    connection_limit: ConnectionLimit,
+    ip_filter: Option<Arc<IpFilter>>,
+}
+
+impl Default for CleanServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Functions for creating and configuring [CleanServer].
+impl CleanServer {
+    #[inline]
+    /// Creating a default instance of a [CleanServer].
+    /// Errors are reported with [ServerError::log].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::CleanServer;
+    /// use tokio::net::{TcpListener, TcpStream};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     CleanServer::new()
+    ///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(mut stream: TcpStream) {}
+    /// ```
+    pub fn new() -> Self {
+        CleanServer {
+            on_error: Arc::new(ServerError::log),
+            connection_limit: ConnectionLimit::default(),
+            ip_filter: None,
+        }
+    }
+
+    #[inline]
+    /// Creating a new instance of a [CleanServer] from a function.
+    ///
+    /// # Parameters
+    /// * `fn_edit` - Function to change [CleanServer].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::CleanServer;
+    ///
+    /// let server = CleanServer::from_fn(|server| {
+    ///     server.set_on_error(|err, addr, phase| println!("{addr:?} | {phase} | {err}"));
+    /// });
+    /// ```
+    pub fn from_fn<F: FnOnce(&mut CleanServer)>(fn_edit: F) -> Self {
+        let mut server = CleanServer::new();
+        fn_edit(&mut server);
+        server
+    }
+
+    #[inline]
+    /// Sets the callback receiving connection-level errors.
+    ///
+    /// # Parameters
+    /// * `on_error` - Function receiving the error, the client address and the [ErrorPhase].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::CleanServer;
+    ///
+    /// let mut server = CleanServer::new();
+    /// server.set_on_error(|err, addr, phase| err.log(addr, phase));
+    /// ```
+    pub fn set_on_error<F>(&mut self, on_error: F)
+    where
+        F: Fn(&ServerError, Option<SocketAddr>, ErrorPhase) + Send + Sync + 'static,
+    {
+        self.on_error = Arc::new(on_error);
+    }
+
+    #[inline]
+    /// Sets the limit of concurrent connections, [ConnectionLimit::max_requests] is ignored.
+    /// With [Saturation::Reject] connections over the limit are closed right away.
+    ///
+    /// # Parameters
+    /// * `connection_limit` - Limit and the behaviour when it is reached.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{CleanServer, ConnectionLimit};
+    ///
+    /// let mut server = CleanServer::new();
+    /// server.set_connection_limit(ConnectionLimit {
+    ///     max_connections: Some(1024),
+    ///     ..ConnectionLimit::default()
+    /// });
+    /// ```
+    pub fn set_connection_limit(&mut self, connection_limit: ConnectionLimit) {
+        self.connection_limit = connection_limit;
+    }
+
+    #[inline]
+    /// Sets the IP allow and deny lists, rejected clients are disconnected before
+    /// `check_fn` runs.
+    ///
+    /// # Parameters
+    /// * `ip_filter` - Shared filter, its rules can be reloaded while the server runs.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{CleanServer, IpFilter};
+    /// use std::sync::Arc;
+    ///
+    /// let mut server = CleanServer::new();
+    /// server.set_ip_filter(Arc::new(IpFilter::from_rules("deny 10.0.0.0/8").unwrap()));
+    /// ```
+    pub fn set_ip_filter(&mut self, ip_filter: Arc<IpFilter>) {
+        self.ip_filter = Some(ip_filter);
+    }
+}
 
 /// Built-in functions for reading and writing.
 impl CleanServer {
@@ -126,7 +246,7 @@ impl CleanServer {
 
 /// Functions for starting and running the server.
 impl CleanServer {
-    /// Starting the server.
+    /// Starting the server with the default configuration.
     ///
     /// # Parameters
     /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
@@ -150,17 +270,236 @@ impl CleanServer {
     ) where
         Fut: Future<Output = ()> + Send + 'static,
     {
-        println!(
-            "SERVER | CLEAN | {} | LAUCNH",
-            listener.local_addr().unwrap()
-        );
+        CleanServer::new().run(listener, function).await;
+    }
 
-        loop {
-            if let Ok((socket, _)) = listener.accept().await {
-                tokio::spawn(async move {
-                    function(socket).await;
-                });
+    #[cfg(feature = "check_stream")]
+    /// Starting the server with the default configuration.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `function` - Asynchronous function for working with TcpStream.
+    /// * `check_fn` - Asynchronous function to check TcpStream, returns true if valid.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::CleanServer;
+    /// use tokio::net::{TcpListener, TcpStream};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     CleanServer::launch_with_check(
+    ///         TcpListener::bind("127.0.0.1:80").await.unwrap(),
+    ///         work,
+    ///         check,
+    ///     ).await;
+    /// }
+    ///
+    /// async fn check(_: std::net::SocketAddr) -> bool { true }
+    /// async fn work(mut stream: TcpStream) {}
+    /// ```
+    pub async fn launch_with_check<FutC, Fut>(
+        listener: TcpListener,
+        function: impl Fn(tokio::net::TcpStream) -> Fut + Send + Copy + Sync + 'static,
+        check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
+    ) where
+        FutC: Future<Output = bool> + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        CleanServer::new()
+            .run_with_check(listener, function, check_fn)
+            .await;
+    }
+
+    /// Starting the configured server.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `function` - Asynchronous function for working with TcpStream.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::CleanServer;
+    /// use tokio::net::{TcpListener, TcpStream};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     CleanServer::from_fn(|server| server.set_on_error(|_, _, _| {}))
+    ///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(mut stream: TcpStream) {}
+    /// ```
+    pub async fn run<Fut>(
+        self,
+        listener: TcpListener,
+        function: impl Fn(tokio::net::TcpStream) -> Fut + Send + Copy + Sync + 'static,
+    ) where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        async fn check(_: SocketAddr) -> bool {
+            true
+        }
+
+        self.impl_launch(listener, move |socket, _| function(socket), check)
+            .await;
+    }
+
+    #[cfg(feature = "check_stream")]
+    /// Starting the configured server.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `function` - Asynchronous function for working with TcpStream.
+    /// * `check_fn` - Asynchronous function to check TcpStream, returns true if valid.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::CleanServer;
+    /// use tokio::net::{TcpListener, TcpStream};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     CleanServer::from_fn(|server| server.set_on_error(|_, _, _| {}))
+    ///         .run_with_check(TcpListener::bind("127.0.0.1:80").await.unwrap(), work, check)
+    ///         .await;
+    /// }
+    ///
+    /// async fn check(_: std::net::SocketAddr) -> bool { true }
+    /// async fn work(mut stream: TcpStream) {}
+    /// ```
+    pub async fn run_with_check<FutC, Fut>(
+        self,
+        listener: TcpListener,
+        function: impl Fn(tokio::net::TcpStream) -> Fut + Send + Copy + Sync + 'static,
+        check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
+    ) where
+        FutC: Future<Output = bool> + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.impl_launch(listener, move |socket, _| function(socket), check_fn)
+            .await;
+    }
+
+    #[cfg(feature = "tls")]
+    /// Starting the configured server with TLS.
+    /// The function receives the connection after the handshake.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `tls` - Certificate chain, private key and handshake timeout.
+    /// * `function` - Asynchronous function for working with TlsStream.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{CleanServer, TlsConfig, TlsStream};
+    /// use tokio::net::{TcpListener, TcpStream};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").await.unwrap();
+    ///
+    ///     CleanServer::new()
+    ///         .run_tls(TcpListener::bind("127.0.0.1:443").await.unwrap(), tls, work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(mut stream: TlsStream<TcpStream>) {
+    ///     CleanServer::write(&mut stream, "Sample text xD").await.unwrap();
+    /// }
+    /// ```
+    pub async fn run_tls<Fut>(
+        self,
+        listener: TcpListener,
+        tls: TlsConfig,
+        function: impl Fn(TlsStream<TcpStream>) -> Fut + Send + Copy + Sync + 'static,
+    ) where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        async fn check(_: SocketAddr) -> bool {
+            true
+        }
+
+        let on_error = self.on_error.clone();
+        let serve = move |socket, addr| {
+            let (tls, on_error) = (tls.clone(), on_error.clone());
+
+            async move {
+                match tls.accept(socket).await {
+                    Ok((stream, _)) => function(stream).await,
+                    Err(err) => on_error(&err, Some(addr), ErrorPhase::Handshake),
+                }
             }
+        };
+
+        self.impl_launch(listener, serve, check).await;
+    }
+
+    async fn impl_launch<FutC, Fut>(
+        self,
+        listener: TcpListener,
+        function: impl Fn(TcpStream, SocketAddr) -> Fut + Send + Clone + Sync + 'static,
+        check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
+    ) where
+        FutC: Future<Output = bool> + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if let Ok(addr) = listener.local_addr() {
+            log_event!(info, "SERVER | CLEAN | {addr} | LAUNCH");
+        }
+
+        let limit = self.connection_limit;
+        let slots = ConnectionLimit::semaphore(limit.max_connections);
+
+        loop {
+            let waited = match limit.saturation {
+                Saturation::Wait => limit.acquire(&slots).await.unwrap_or(None),
+                Saturation::Reject { .. } => None,
+            };
+
+            let (socket, addr) = match listener.accept().await {
+                Ok((socket, addr)) => (socket, addr),
+                Err(err) => {
+                    (self.on_error)(&ServerError::Accept(err), None, ErrorPhase::Accept);
+                    continue;
+                }
+            };
+
+            let permit = match waited {
+                Some(permit) => Some(permit),
+                None => match limit.acquire(&slots).await {
+                    Ok(permit) => permit,
+                    Err(()) => {
+                        let err = ServerError::ConnectionLimit;
+                        (self.on_error)(&err, Some(addr), ErrorPhase::Accept);
+                        continue;
+                    }
+                },
+            };
+
+            let on_error = self.on_error.clone();
+            let ip_filter = self.ip_filter.clone();
+            let function = function.clone();
+            let connection = async move {
+                let _permit = permit;
+
+                let allowed = ip_filter.is_none_or(|filter| filter.check(addr));
+                if !allowed || !check_fn(addr).await {
+                    let err = ServerError::VerificationFailed;
+                    return on_error(&err, Some(addr), ErrorPhase::Check);
+                }
+
+                function(socket, addr).await;
+            };
+
+            #[cfg(feature = "tracing")]
+            let connection = tracing::Instrument::instrument(
+                connection,
+                tracing::info_span!("connection", peer = %addr),
+            );
+
+            tokio::spawn(connection);
         }
     }
 }
@@ -0,0 +1,100 @@
+use crate::*;
+
+/// Behaviour of a server whose connection or request limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Saturation {
+    /// Stop accepting new connections (or starting new requests) until a slot is free.
+    Wait,
+    /// Accept the connection and immediately answer `503 Service Unavailable` with
+    /// `Retry-After`, then close it. [CleanServer] and [HttpServer] serving TLS just close
+    /// the connection.
+    Reject {
+        /// Value of the `Retry-After` header, rounded up to whole seconds and at least 1.
+        retry_after: Duration,
+    },
+}
+
+/// Limits of concurrently served connections and requests, [None] disables the limit.
+///
+/// # Examples
+/// ```
+/// use maker_web::{ConnectionLimit, HttpServer, Saturation};
+/// use std::time::Duration;
+///
+/// let server = HttpServer::from_fn(|server| {
+///     server.set_connection_limit(ConnectionLimit {
+///         max_connections: Some(10_000),
+///         max_requests: Some(512),
+///         saturation: Saturation::Reject { retry_after: Duration::from_secs(5) },
+///     });
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionLimit {
+    /// Maximum number of open connections.
+    pub max_connections: Option<usize>,
+    /// Maximum number of requests whose handler is running, only used by [HttpServer].
+    pub max_requests: Option<usize>,
+    /// What to do when a limit is reached.
+    pub saturation: Saturation,
+}
+
+impl Default for ConnectionLimit {
+    /// No limits, waiting when saturated.
+    fn default() -> Self {
+        ConnectionLimit {
+            max_connections: None,
+            max_requests: None,
+            saturation: Saturation::Wait,
+        }
+    }
+}
+
+/// Permit of a connection or request slot, released when dropped.
+pub(crate) type Permit = Option<OwnedSemaphorePermit>;
+
+/// Functions for acquiring slots of [ConnectionLimit].
+impl ConnectionLimit {
+    #[inline]
+    pub(crate) fn semaphore(limit: Option<usize>) -> Option<Arc<Semaphore>> {
+        limit.map(|limit| Arc::new(Semaphore::new(limit)))
+    }
+
+    #[inline]
+    /// Takes a slot, waiting for it with [Saturation::Wait].
+    /// Returns [Err] if the slot is not available with [Saturation::Reject].
+    pub(crate) async fn acquire(&self, semaphore: &Option<Arc<Semaphore>>) -> Result<Permit, ()> {
+        let Some(semaphore) = semaphore else {
+            return Ok(None);
+        };
+
+        match self.saturation {
+            Saturation::Wait => semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .map(Some)
+                .map_err(drop),
+            Saturation::Reject { .. } => semaphore
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(drop),
+        }
+    }
+
+    #[inline]
+    /// Response sent when a slot is not available.
+    pub(crate) fn response(&self, err: &ServerError) -> Response {
+        let mut response = err.response();
+
+        if let Saturation::Reject { retry_after } = self.saturation {
+            let secs = retry_after
+                .as_secs()
+                .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+            response.add_header("Retry-After", itoa::Buffer::new().format(secs.max(1)));
+        }
+
+        response
+    }
+}
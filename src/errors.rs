@@ -11,6 +11,9 @@ pub enum ServerError {
     /// Unknown request method.
     #[error("Unknown request method")]
     InvalidMethod(http::method::InvalidMethod),
+    /// The request uses an HTTP version other than 1.0 and 1.1.
+    #[error("Unsupported HTTP version: {0}")]
+    UnsupportedVersion(String),
     /// Error parsing URL.
     #[error("Error parsing URL")]
     InvalidUrl(http::uri::InvalidUri),
@@ -23,6 +26,30 @@ pub enum ServerError {
     /// Error parsing header value.
     #[error("Error parsing header value")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
+    /// `Content-Length` is not a number, is repeated with different values
+    /// or is sent together with `Transfer-Encoding`.
+    #[error("Invalid Content-Length")]
+    InvalidContentLength,
+    /// The request body uses a transfer coding other than `chunked`.
+    #[error("Unsupported Transfer-Encoding")]
+    UnsupportedTransferEncoding,
+    /// The chunked request body is malformed.
+    #[error("Invalid chunked body")]
+    InvalidChunk,
+
+    /* --- Request size errors --- */
+    /// The request line is longer than [Limits::request_line].
+    #[error("The request line is too long")]
+    RequestLineTooLong,
+    /// The headers are larger than [Limits::header_bytes].
+    #[error("The request headers are too large")]
+    HeadersTooLarge,
+    /// There are more headers than [Limits::header_count].
+    #[error("The request has too many headers")]
+    TooManyHeaders,
+    /// The body is larger than [Limits::body].
+    #[error("The request body is too large")]
+    BodyTooLarge,
 
     /* --- Read/write errors --- */
     /// Indicates that the request is empty or took too long to receive.
@@ -40,11 +67,49 @@ pub enum ServerError {
     /// Indicates an error occurred while flushing.
     #[error("Flush error")]
     Flush(std::io::Error),
+    /// Indicates that the client is gone before the streamed body ended.
+    #[error("The response stream is closed")]
+    StreamClosed,
+    /// The body of the response is streamed, e.g. a file, and cannot be turned into bytes.
+    #[error("The response body is streamed")]
+    StreamedBody,
+
+    /* --- Timeout errors --- */
+    /// The request line and headers were not received within [Timeouts::head].
+    #[error("Reading the request head took too long")]
+    HeadTimeout,
+    /// The request body was not received within [Timeouts::body].
+    #[error("Reading the request body took too long")]
+    BodyTimeout,
+    /// The handler did not return within [Timeouts::handler].
+    #[error("The handler took too long")]
+    HandlerTimeout,
+    /// The response was not written within [Timeouts::write].
+    #[error("Writing the response took too long")]
+    WriteTimeout,
+    /// No new request arrived within [Timeouts::keep_alive].
+    #[error("The connection was idle for too long")]
+    IdleTimeout,
 
     /* --- Network errors --- */
+    /// Indicates an error occurred while accepting a new connection.
+    #[error("Accepting error")]
+    Accept(std::io::Error),
+    /// The limit of concurrent connections is reached.
+    #[error("Too many concurrent connections")]
+    ConnectionLimit,
+    /// The limit of concurrently handled requests is reached.
+    #[error("Too many concurrent requests")]
+    RequestLimit,
+    /// The client exceeded the [RateLimiter] limit.
+    #[error("Too many requests from the client")]
+    RateLimited,
     /// Indicates that a TcpStream failed validation.
     #[error("TcpStream failed validation")]
     VerificationFailed,
+    /// A rule of [IpFilter] is not a valid `allow <cidr>` or `deny <cidr>` line.
+    #[error("Invalid IP filter rule: {0}")]
+    InvalidIpRule(String),
     #[cfg(feature = "get_stream")]
     /// Indicates an error occurred when getting the Socket Address.
     #[error("Error getting Socket Adder")]
@@ -53,6 +118,36 @@ pub enum ServerError {
     #[error("This HTTP status does not exist: {0}")]
     UnknownHttpStatus(u16),
 
+    /* --- TLS errors --- */
+    #[cfg(feature = "tls")]
+    /// The certificate chain or the private key could not be loaded.
+    #[error("Invalid certificate or private key: {0}")]
+    InvalidCertificate(String),
+    #[cfg(feature = "tls")]
+    /// The TLS handshake failed.
+    #[error("TLS handshake error")]
+    Handshake(std::io::Error),
+    #[cfg(feature = "tls")]
+    /// The TLS handshake did not complete within the handshake timeout of [TlsConfig].
+    #[error("The TLS handshake took too long")]
+    HandshakeTimeout,
+
+    /* --- HTTP/2 errors --- */
+    #[cfg(feature = "http2")]
+    /// The HTTP/2 connection or stream failed.
+    #[error("HTTP/2 error: {0}")]
+    Http2(h2::Error),
+
+    /* --- WebSocket errors --- */
+    #[cfg(feature = "websocket")]
+    /// The request is not a valid WebSocket upgrade request.
+    #[error("Invalid WebSocket upgrade request: {0}")]
+    InvalidUpgrade(&'static str),
+    #[cfg(feature = "websocket")]
+    /// The WebSocket connection broke the protocol, with the close code sent to the peer.
+    #[error("WebSocket error {0}: {1}")]
+    WebSocket(u16, &'static str),
+
     /* --- File system errors --- */
     /// The provided path is not a file.
     #[error("The provided path is not a file")]
@@ -60,4 +155,258 @@ pub enum ServerError {
     /// The file is missing or something is blocking it from opening
     #[error("The file is missing or something is blocking it from opening")]
     OpeningFile(std::io::Error),
+    /// The requested path is malformed or leads out of the served directory.
+    #[error("Unsafe path: {0}")]
+    UnsafePath(&'static str),
+}
+
+/// Stage of connection handling at which a [ServerError] occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorPhase {
+    /// Accepting a connection from the listener.
+    Accept,
+    /// Checking the client address with `check_fn`.
+    Check,
+    /// Performing the TLS handshake.
+    Handshake,
+    /// Reading and parsing the request.
+    Read,
+    /// Running the handler.
+    Handle,
+    /// Writing the response.
+    Write,
+    /// Writing the access log.
+    Log,
+}
+
+impl fmt::Display for ErrorPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorPhase::Accept => "ACCEPT",
+            ErrorPhase::Check => "CHECK",
+            ErrorPhase::Handshake => "HANDSHAKE",
+            ErrorPhase::Read => "READ",
+            ErrorPhase::Handle => "HANDLE",
+            ErrorPhase::Write => "WRITE",
+            ErrorPhase::Log => "LOG",
+        })
+    }
+}
+
+/// Callback receiving connection-level errors, the client address (if known) and the phase.
+pub type ErrorHook = Arc<dyn Fn(&ServerError, Option<SocketAddr>, ErrorPhase) + Send + Sync>;
+
+/// Functions for reporting [ServerError].
+impl ServerError {
+    #[inline]
+    /// Name of the error variant, used as a metrics label.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::ServerError;
+    ///
+    /// assert_eq!(ServerError::BrokenFirstLine.kind(), "BrokenFirstLine");
+    /// ```
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerError::BrokenFirstLine => "BrokenFirstLine",
+            ServerError::InvalidMethod(_) => "InvalidMethod",
+            ServerError::UnsupportedVersion(_) => "UnsupportedVersion",
+            ServerError::InvalidUrl(_) => "InvalidUrl",
+            ServerError::InvalidHeader => "InvalidHeader",
+            ServerError::InvalidHeaderName(_) => "InvalidHeaderName",
+            ServerError::InvalidHeaderValue(_) => "InvalidHeaderValue",
+            ServerError::InvalidContentLength => "InvalidContentLength",
+            ServerError::UnsupportedTransferEncoding => "UnsupportedTransferEncoding",
+            ServerError::InvalidChunk => "InvalidChunk",
+
+            ServerError::RequestLineTooLong => "RequestLineTooLong",
+            ServerError::HeadersTooLarge => "HeadersTooLarge",
+            ServerError::TooManyHeaders => "TooManyHeaders",
+            ServerError::BodyTooLarge => "BodyTooLarge",
+
+            ServerError::EmptyRequest => "EmptyRequest",
+            ServerError::EmptyLine => "EmptyLine",
+            ServerError::Read(_) => "Read",
+            ServerError::Write(_) => "Write",
+            ServerError::Flush(_) => "Flush",
+            ServerError::StreamClosed => "StreamClosed",
+            ServerError::StreamedBody => "StreamedBody",
+
+            ServerError::HeadTimeout => "HeadTimeout",
+            ServerError::BodyTimeout => "BodyTimeout",
+            ServerError::HandlerTimeout => "HandlerTimeout",
+            ServerError::WriteTimeout => "WriteTimeout",
+            ServerError::IdleTimeout => "IdleTimeout",
+
+            ServerError::Accept(_) => "Accept",
+            ServerError::ConnectionLimit => "ConnectionLimit",
+            ServerError::RequestLimit => "RequestLimit",
+            ServerError::RateLimited => "RateLimited",
+            ServerError::VerificationFailed => "VerificationFailed",
+            ServerError::InvalidIpRule(_) => "InvalidIpRule",
+            #[cfg(feature = "get_stream")]
+            ServerError::GetSocketAddr => "GetSocketAddr",
+            ServerError::UnknownHttpStatus(_) => "UnknownHttpStatus",
+
+            #[cfg(feature = "tls")]
+            ServerError::InvalidCertificate(_) => "InvalidCertificate",
+            #[cfg(feature = "tls")]
+            ServerError::Handshake(_) => "Handshake",
+            #[cfg(feature = "tls")]
+            ServerError::HandshakeTimeout => "HandshakeTimeout",
+
+            #[cfg(feature = "http2")]
+            ServerError::Http2(_) => "Http2",
+
+            #[cfg(feature = "websocket")]
+            ServerError::InvalidUpgrade(_) => "InvalidUpgrade",
+            #[cfg(feature = "websocket")]
+            ServerError::WebSocket(..) => "WebSocket",
+
+            ServerError::FolderInsteadFile => "FolderInsteadFile",
+            ServerError::OpeningFile(_) => "OpeningFile",
+            ServerError::UnsafePath(_) => "UnsafePath",
+        }
+    }
+
+    #[inline]
+    /// Default error hook of [HttpServer] and [CleanServer].
+    /// The error is written through `tracing` or to stderr, depending on the enabled features.
+    ///
+    /// # Parameters
+    /// * `addr` - Client socket address, if the error happened after accepting.
+    /// * `phase` - Stage of connection handling.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{ErrorPhase, ServerError};
+    ///
+    /// let addr = "127.0.0.1:8080".parse().ok();
+    /// ServerError::BrokenFirstLine.log(addr, ErrorPhase::Read);
+    /// ```
+    pub fn log(&self, addr: Option<SocketAddr>, phase: ErrorPhase) {
+        let addr = match addr {
+            Some(addr) => addr.to_string(),
+            None => String::from("-"),
+        };
+
+        match phase {
+            ErrorPhase::Accept => log_event!(error, "SERVER | ERROR | {addr} | {phase} | {self}"),
+            ErrorPhase::Handle | ErrorPhase::Write | ErrorPhase::Log => {
+                log_event!(warn, "SERVER | ERROR | {addr} | {phase} | {self}")
+            }
+            ErrorPhase::Check | ErrorPhase::Handshake | ErrorPhase::Read => {
+                log_event!(debug, "SERVER | ERROR | {addr} | {phase} | {self}")
+            }
+        }
+    }
+}
+
+/// Functions for mapping [ServerError] into HTTP responses.
+impl ServerError {
+    #[inline]
+    /// HTTP status code that should be sent to the client for this error.
+    /// Returns [None] if nothing should be sent, e.g. the connection is broken.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::ServerError;
+    /// use http::StatusCode;
+    ///
+    /// assert_eq!(ServerError::BrokenFirstLine.status_code(), Some(StatusCode::BAD_REQUEST));
+    /// assert_eq!(ServerError::EmptyRequest.status_code(), None);
+    /// ```
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            ServerError::BrokenFirstLine
+            | ServerError::InvalidUrl(_)
+            | ServerError::InvalidHeader
+            | ServerError::InvalidHeaderName(_)
+            | ServerError::InvalidHeaderValue(_)
+            | ServerError::InvalidContentLength
+            | ServerError::InvalidChunk => Some(StatusCode::BAD_REQUEST),
+            ServerError::InvalidMethod(_) | ServerError::UnsupportedTransferEncoding => {
+                Some(StatusCode::NOT_IMPLEMENTED)
+            }
+            ServerError::UnsupportedVersion(_) => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+
+            ServerError::RequestLineTooLong => Some(StatusCode::URI_TOO_LONG),
+            ServerError::HeadersTooLarge | ServerError::TooManyHeaders => {
+                Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+            }
+            ServerError::BodyTooLarge => Some(StatusCode::PAYLOAD_TOO_LARGE),
+
+            ServerError::EmptyRequest
+            | ServerError::EmptyLine
+            | ServerError::Read(_)
+            | ServerError::Write(_)
+            | ServerError::Flush(_)
+            | ServerError::StreamClosed
+            | ServerError::StreamedBody => None,
+
+            ServerError::HeadTimeout | ServerError::BodyTimeout => {
+                Some(StatusCode::REQUEST_TIMEOUT)
+            }
+            ServerError::HandlerTimeout => Some(StatusCode::SERVICE_UNAVAILABLE),
+            ServerError::WriteTimeout | ServerError::IdleTimeout => None,
+
+            ServerError::ConnectionLimit | ServerError::RequestLimit => {
+                Some(StatusCode::SERVICE_UNAVAILABLE)
+            }
+            ServerError::RateLimited => Some(StatusCode::TOO_MANY_REQUESTS),
+            ServerError::Accept(_)
+            | ServerError::VerificationFailed
+            | ServerError::InvalidIpRule(_) => None,
+            #[cfg(feature = "get_stream")]
+            ServerError::GetSocketAddr => None,
+            ServerError::UnknownHttpStatus(_) => Some(StatusCode::INTERNAL_SERVER_ERROR),
+
+            #[cfg(feature = "tls")]
+            ServerError::InvalidCertificate(_)
+            | ServerError::Handshake(_)
+            | ServerError::HandshakeTimeout => None,
+
+            #[cfg(feature = "http2")]
+            ServerError::Http2(_) => None,
+
+            #[cfg(feature = "websocket")]
+            ServerError::InvalidUpgrade(_) => Some(StatusCode::BAD_REQUEST),
+            #[cfg(feature = "websocket")]
+            ServerError::WebSocket(..) => None,
+
+            ServerError::FolderInsteadFile | ServerError::OpeningFile(_) => {
+                Some(StatusCode::NOT_FOUND)
+            }
+            ServerError::UnsafePath(_) => Some(StatusCode::FORBIDDEN),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn response(&self) -> Response {
+        let status = self
+            .status_code()
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        Response::from_response(status, status.canonical_reason().unwrap_or_default())
+    }
+}
+
+/// Allows handlers to return `Result<_, ServerError>`.
+///
+/// # Examples
+/// ```
+/// use maker_web::{IntoResponse, ServerError};
+/// use http::StatusCode;
+///
+/// let response = ServerError::FolderInsteadFile.into_response();
+///
+/// assert_eq!(response.status_code, StatusCode::NOT_FOUND);
+/// assert_eq!(response.body.as_ref(), b"\r\nNot Found");
+/// ```
+impl IntoResponse for ServerError {
+    #[inline]
+    fn into_response(self) -> Response {
+        self.response()
+    }
 }
@@ -0,0 +1,518 @@
+use crate::*;
+
+/// Format of the lines written by [AccessLog].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogFormat {
+    /// Common Log Format: `host - - [time] "request" status size`.
+    Common,
+    /// Combined Log Format: Common Log Format followed by `"referer" "user agent"`.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Rotation policy of an [AccessLog] file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    /// The file grows forever.
+    Never,
+    /// When the file reaches `max_bytes` it is renamed to `<path>.1`, older files are
+    /// shifted to `<path>.2` and so on, at most `keep` old files are kept.
+    Size {
+        /// Maximum size of the current file in bytes.
+        max_bytes: u64,
+        /// Number of rotated files to keep.
+        keep: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single served request, as recorded by [AccessLog].
+pub struct AccessEntry {
+    /// Client socket address.
+    pub socket_addr: SocketAddr,
+    /// HTTP request method, [None] if the request could not be parsed.
+    pub method: Option<Method>,
+    /// Requested URL, [None] if the request could not be parsed.
+    pub url: Option<Uri>,
+    /// HTTP version of the request, [None] if the request could not be parsed.
+    pub version: Option<Version>,
+    /// Response status code.
+    pub status: StatusCode,
+    /// Size of the response body in bytes.
+    pub size: usize,
+    /// Value of the `Referer` request header.
+    pub referer: Option<HeaderValue>,
+    /// Value of the `User-Agent` request header.
+    pub user_agent: Option<HeaderValue>,
+    /// Time from the parsed request to the written response.
+    pub duration: Duration,
+    /// Time at which the request was received.
+    pub time: SystemTime,
+}
+
+/// Functions for formatting [AccessEntry].
+impl AccessEntry {
+    #[inline]
+    /// Formats the entry as a single log line, without the trailing line break.
+    ///
+    /// # Parameters
+    /// * `format` - Log line format.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{AccessEntry, LogFormat};
+    /// use http::{Method, StatusCode, HeaderValue, Version};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let entry = AccessEntry {
+    ///     socket_addr: "127.0.0.1:4000".parse().unwrap(),
+    ///     method: Some(Method::GET),
+    ///     url: Some("/index.html".parse().unwrap()),
+    ///     version: Some(Version::HTTP_11),
+    ///     status: StatusCode::OK,
+    ///     size: 2326,
+    ///     referer: None,
+    ///     user_agent: Some(HeaderValue::from_static("curl/8.0")),
+    ///     duration: Duration::from_micros(1500),
+    ///     time: SystemTime::UNIX_EPOCH + Duration::from_secs(971186136),
+    /// };
+    ///
+    /// assert_eq!(
+    ///     entry.format(LogFormat::Common),
+    ///     "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /index.html HTTP/1.1\" 200 2326"
+    /// );
+    /// assert_eq!(
+    ///     entry.format(LogFormat::Combined),
+    ///     "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /index.html HTTP/1.1\" 200 2326 \"-\" \"curl/8.0\""
+    /// );
+    /// assert_eq!(
+    ///     entry.format(LogFormat::Json),
+    ///     "{\"time\":\"2000-10-10T13:55:36Z\",\"remote_addr\":\"127.0.0.1:4000\",\"method\":\"GET\",\
+    ///     \"url\":\"/index.html\",\"protocol\":\"HTTP/1.1\",\"status\":200,\"size\":2326,\"referer\":null,\
+    ///     \"user_agent\":\"curl/8.0\",\"duration_us\":1500}"
+    /// );
+    /// ```
+    /// A request that could not be parsed is logged with `-` as the request line:
+    /// ```
+    /// use maker_web::{AccessEntry, LogFormat};
+    /// use http::StatusCode;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let entry = AccessEntry {
+    ///     socket_addr: "127.0.0.1:4000".parse().unwrap(),
+    ///     method: None,
+    ///     url: None,
+    ///     version: None,
+    ///     status: StatusCode::BAD_REQUEST,
+    ///     size: 0,
+    ///     referer: None,
+    ///     user_agent: None,
+    ///     duration: Duration::ZERO,
+    ///     time: SystemTime::UNIX_EPOCH + Duration::from_secs(971186136),
+    /// };
+    ///
+    /// assert_eq!(
+    ///     entry.format(LogFormat::Common),
+    ///     "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"-\" 400 -"
+    /// );
+    /// ```
+    /// Backslashes and quotes in the request, `Referer` and `User-Agent` are escaped:
+    /// ```
+    /// use maker_web::{AccessEntry, LogFormat};
+    /// use http::{HeaderValue, Method, StatusCode, Version};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let entry = AccessEntry {
+    ///     socket_addr: "127.0.0.1:4000".parse().unwrap(),
+    ///     method: Some(Method::GET),
+    ///     url: Some("/a\"b\\c".parse().unwrap()),
+    ///     version: Some(Version::HTTP_11),
+    ///     status: StatusCode::OK,
+    ///     size: 0,
+    ///     referer: Some(HeaderValue::from_static("a\\")),
+    ///     user_agent: Some(HeaderValue::from_static("b\" \"c")),
+    ///     duration: Duration::ZERO,
+    ///     time: SystemTime::UNIX_EPOCH + Duration::from_secs(971186136),
+    /// };
+    ///
+    /// assert_eq!(
+    ///     entry.format(LogFormat::Combined),
+    ///     r#"127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /a\"b\\c HTTP/1.1" 200 - "a\\" "b\" \"c""#
+    /// );
+    /// ```
+    pub fn format(&self, format: LogFormat) -> String {
+        let (year, month, day, hour, min, sec) = civil_time(self.time);
+        let size = match self.size {
+            0 => String::from("-"),
+            size => size.to_string(),
+        };
+
+        match format {
+            LogFormat::Common | LogFormat::Combined => {
+                let request = match (&self.method, &self.url, self.version) {
+                    (Some(method), Some(url), Some(version)) => {
+                        escape_quoted(&format!("{method} {url} {version:?}"))
+                    }
+                    _ => String::from("-"),
+                };
+                let mut line = format!(
+                    "{} - - [{day:02}/{}/{year}:{hour:02}:{min:02}:{sec:02} +0000] \"{request}\" {} {size}",
+                    self.socket_addr.ip(),
+                    MONTHS[month as usize - 1],
+                    self.status.as_u16(),
+                );
+
+                if format == LogFormat::Combined {
+                    line.push_str(" \"");
+                    line.push_str(&quoted_header(&self.referer));
+                    line.push_str("\" \"");
+                    line.push_str(&quoted_header(&self.user_agent));
+                    line.push('"');
+                }
+
+                line
+            }
+            LogFormat::Json => format!(
+                "{{\"time\":\"{year}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}Z\",\
+                \"remote_addr\":\"{}\",\"method\":{},\"url\":{},\"protocol\":{},\"status\":{},\"size\":{},\
+                \"referer\":{},\"user_agent\":{},\"duration_us\":{}}}",
+                self.socket_addr,
+                json_option(self.method.as_ref().map(Method::as_str)),
+                json_option(self.url.as_ref().map(Uri::to_string).as_deref()),
+                json_option(self.version.map(|version| format!("{version:?}")).as_deref()),
+                self.status.as_u16(),
+                self.size,
+                json_header(&self.referer),
+                json_header(&self.user_agent),
+                self.duration.as_micros(),
+            ),
+        }
+    }
+}
+
+/// Number of entries queued for the writer before [AccessLog::write] waits.
+const QUEUE: usize = 1024;
+/// Number of bytes written and flushed at once by the writer.
+const BATCH: usize = 64 * 1024;
+
+/// Access log of [HttpServer].
+///
+/// Entries are queued to a single writer task, started on the first entry, that writes
+/// and flushes them in batches, so a slow sink does not hold up the connections.
+pub struct AccessLog {
+    format: LogFormat,
+    sender: once_cell::sync::OnceCell<mpsc::Sender<String>>,
+    sink: Mutex<Option<Sink>>,
+    error: Arc<Mutex<Option<ServerError>>>,
+}
+
+enum Sink {
+    Writer(Box<dyn AsyncWrite + Send + Unpin>),
+    File {
+        path: PathBuf,
+        file: Option<File>,
+        size: u64,
+        rotation: Rotation,
+    },
+}
+
+/// Functions for creating [AccessLog].
+impl AccessLog {
+    #[inline]
+    /// Creating a new instance of a [AccessLog] writing into any asynchronous writer.
+    ///
+    /// # Parameters
+    /// * `writer` - Destination of the log lines.
+    /// * `format` - Log line format.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{AccessLog, HttpServer, LogFormat, Request, Response};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     HttpServer::from_fn(|server| {
+    ///         server.set_access_log(AccessLog::new(tokio::io::stdout(), LogFormat::Combined));
+    ///     })
+    ///     .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///     .await;
+    /// }
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     Response::from_body("All Good :)")
+    /// }
+    /// ```
+    pub fn new<W: AsyncWrite + Send + Unpin + 'static>(writer: W, format: LogFormat) -> Self {
+        Self::with_sink(Sink::Writer(Box::new(writer)), format)
+    }
+
+    #[inline]
+    /// Creating a new instance of a [AccessLog] appending to a file.
+    ///
+    /// # Parameters
+    /// * `path` - Path to the log file, created if missing.
+    /// * `format` - Log line format.
+    /// * `rotation` - Rotation policy of the file.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{AccessLog, LogFormat, Rotation};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let rotation = Rotation::Size { max_bytes: 10 * 1024 * 1024, keep: 5 };
+    ///     let log = AccessLog::file("access.log", LogFormat::Json, rotation)
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub async fn file<P: AsRef<Path>>(
+        path: P,
+        format: LogFormat,
+        rotation: Rotation,
+    ) -> Result<Self, ServerError> {
+        let path = path.as_ref().to_path_buf();
+        let file = Sink::open(&path).await?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(ServerError::OpeningFile)?
+            .len();
+
+        let sink = Sink::File {
+            path,
+            file: Some(file),
+            size,
+            rotation,
+        };
+        Ok(Self::with_sink(sink, format))
+    }
+
+    #[inline]
+    fn with_sink(sink: Sink, format: LogFormat) -> Self {
+        AccessLog {
+            format,
+            sender: once_cell::sync::OnceCell::new(),
+            sink: Mutex::new(Some(sink)),
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/// Functions for writing [AccessLog].
+impl AccessLog {
+    #[inline]
+    /// Queues one entry for the writer task, waiting only while the queue is full.
+    /// Returns the first error the writer ran into since the previous call.
+    ///
+    /// # Parameters
+    /// * `entry` - The served request.
+    pub async fn write(&self, entry: &AccessEntry) -> Result<(), ServerError> {
+        let mut line = entry.format(self.format);
+        line.push('\n');
+
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel(QUEUE);
+            let sink = self
+                .sink
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .take();
+            if let Some(sink) = sink {
+                tokio::spawn(Self::run(sink, receiver, Arc::clone(&self.error)));
+            }
+            sender
+        });
+
+        if sender.send(line).await.is_err() {
+            return Err(ServerError::Write(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        match self
+            .error
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+        {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    /// Writer task: writes the queued lines in batches until the log is dropped.
+    async fn run(
+        mut sink: Sink,
+        mut receiver: mpsc::Receiver<String>,
+        error: Arc<Mutex<Option<ServerError>>>,
+    ) {
+        let mut batch = Vec::with_capacity(BATCH);
+
+        while let Some(line) = receiver.recv().await {
+            batch.clear();
+            batch.extend_from_slice(line.as_bytes());
+            while batch.len() < BATCH {
+                match receiver.try_recv() {
+                    Ok(line) => batch.extend_from_slice(line.as_bytes()),
+                    Err(_) => break,
+                }
+            }
+
+            if let Err(err) = sink.write(&batch).await {
+                error
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .get_or_insert(err);
+            }
+        }
+    }
+}
+
+/// Functions for writing and rotating [Sink].
+impl Sink {
+    #[inline]
+    async fn write(&mut self, batch: &[u8]) -> Result<(), ServerError> {
+        match self {
+            Sink::Writer(writer) => {
+                writer.write_all(batch).await.map_err(ServerError::Write)?;
+                writer.flush().await.map_err(ServerError::Flush)
+            }
+            Sink::File {
+                path,
+                file,
+                size,
+                rotation,
+            } => {
+                let current = match file {
+                    Some(current) => current,
+                    None => file.insert(Self::open(path).await?),
+                };
+                current.write_all(batch).await.map_err(ServerError::Write)?;
+                current.flush().await.map_err(ServerError::Flush)?;
+                *size += batch.len() as u64;
+
+                match *rotation {
+                    Rotation::Size { max_bytes, keep } if *size >= max_bytes => {
+                        // The size is reset and the path reopened even when the rotation
+                        // fails, so the error is reported once instead of on every write.
+                        // A file that cannot be reopened is retried on the next batch.
+                        let rotated = Self::rotate(path, keep).await;
+                        *size = 0;
+                        *file = None;
+                        *file = Some(Self::open(path).await?);
+                        rotated
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    #[inline]
+    async fn open(path: &Path) -> Result<File, ServerError> {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(ServerError::OpeningFile)
+    }
+
+    #[inline]
+    async fn rotate(path: &Path, keep: usize) -> Result<(), ServerError> {
+        let rotated = |num: usize| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{num}"));
+            PathBuf::from(name)
+        };
+
+        if keep == 0 {
+            return fs::remove_file(path).await.map_err(ServerError::Write);
+        }
+
+        let _ = fs::remove_file(rotated(keep)).await;
+        for num in (1..keep).rev() {
+            let _ = fs::rename(rotated(num), rotated(num + 1)).await;
+        }
+
+        fs::rename(path, rotated(1))
+            .await
+            .map_err(ServerError::Write)
+    }
+}
+
+pub(crate) const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[inline]
+/// Splits a time into UTC year, month, day, hour, minute and second.
+pub(crate) fn civil_time(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |dur| dur.as_secs()) as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u32);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+#[inline]
+/// Header value for a quoted field of the Combined Log Format.
+fn quoted_header(value: &Option<HeaderValue>) -> String {
+    match value {
+        Some(value) => escape_quoted(&String::from_utf8_lossy(value.as_bytes())),
+        None => String::from("-"),
+    }
+}
+
+#[inline]
+/// Escapes `\` and `"` with a backslash so the value cannot end a quoted field.
+fn escape_quoted(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[inline]
+fn json_header(value: &Option<HeaderValue>) -> String {
+    match value {
+        Some(value) => json_string(&String::from_utf8_lossy(value.as_bytes())),
+        None => String::from("null"),
+    }
+}
+
+#[inline]
+fn json_option(value: Option<&str>) -> String {
+    value.map_or_else(|| String::from("null"), json_string)
+}
+
+#[inline]
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+
+    out.push('"');
+    out
+}
@@ -0,0 +1,217 @@
+use crate::http_11::access_log::{civil_time, MONTHS};
+use crate::http_11::range::number;
+use crate::*;
+
+/// Weekday names from Thursday, the weekday of the Unix epoch.
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// Conditional headers of a request, evaluated against the validators of a response.
+#[derive(Debug, Clone)]
+pub(crate) struct ConditionalRequest {
+    method: Method,
+    if_match: Option<HeaderValue>,
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<HeaderValue>,
+    if_unmodified_since: Option<HeaderValue>,
+}
+
+/// Functions for creating and evaluating [ConditionalRequest].
+impl ConditionalRequest {
+    #[inline]
+    /// Takes the conditional headers of the request.
+    ///
+    /// # Parameters
+    /// * `request` - Request passed to the handler.
+    pub(crate) fn from_request(request: &Request) -> Self {
+        ConditionalRequest {
+            method: request.method.clone(),
+            if_match: request.headers.get(IF_MATCH).cloned(),
+            if_none_match: request.headers.get(IF_NONE_MATCH).cloned(),
+            if_modified_since: request.headers.get(IF_MODIFIED_SINCE).cloned(),
+            if_unmodified_since: request.headers.get(IF_UNMODIFIED_SINCE).cloned(),
+        }
+    }
+
+    #[inline]
+    /// Status to answer instead of performing the request, in the order of RFC 9110:
+    /// `If-Match`, else `If-Unmodified-Since`, fail with 412, then `If-None-Match`,
+    /// else `If-Modified-Since` for `GET` and `HEAD`, answer 304 (412 for other methods).
+    ///
+    /// # Parameters
+    /// * `etag` - Entity tag of the current representation, e.g. `"v1"` or `W/"v1"`.
+    /// * `last_modified` - Modification time of the current representation.
+    pub(crate) fn evaluate(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<StatusCode> {
+        let read = self.method == Method::GET || self.method == Method::HEAD;
+        let modified = last_modified.map(unix_secs);
+
+        match header_text(&self.if_match) {
+            Some(if_match) if !tags_match(if_match, etag, true) => {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+            Some(_) => {}
+            None => {
+                let since = header_text(&self.if_unmodified_since).and_then(parse_http_date);
+                if let (Some(since), Some(modified)) = (since, modified) {
+                    if modified > unix_secs(since) {
+                        return Some(StatusCode::PRECONDITION_FAILED);
+                    }
+                }
+            }
+        }
+
+        match header_text(&self.if_none_match) {
+            Some(if_none_match) if tags_match(if_none_match, etag, false) => Some(match read {
+                true => StatusCode::NOT_MODIFIED,
+                false => StatusCode::PRECONDITION_FAILED,
+            }),
+            Some(_) => None,
+            None if read => {
+                let since = header_text(&self.if_modified_since).and_then(parse_http_date)?;
+                (modified? <= unix_secs(since)).then_some(StatusCode::NOT_MODIFIED)
+            }
+            None => None,
+        }
+    }
+
+    #[inline]
+    /// Answers a `GET` or `HEAD` request whose preconditions fail against the `ETag`
+    /// and `Last-Modified` headers of the 2xx response with an empty 304 or 412.
+    ///
+    /// # Parameters
+    /// * `response` - Response of the handler.
+    pub(crate) fn apply(&self, response: &mut Response) {
+        let read = self.method == Method::GET || self.method == Method::HEAD;
+        if !read || !response.status_code.is_success() {
+            return;
+        }
+
+        let last_modified = response.header("Last-Modified").and_then(parse_http_date);
+        let Some(status) = self.evaluate(response.header("ETag"), last_modified) else {
+            return;
+        };
+
+        response.status_code = status;
+        response.body = BytesMut::from(&b"\r\n"[..]);
+        response.stream = None;
+        for name in ["Content-Type", "Content-Length", "Content-Range"] {
+            response.remove_header(name);
+        }
+    }
+}
+
+#[inline]
+fn header_text(value: &Option<HeaderValue>) -> Option<&str> {
+    value.as_ref().and_then(|value| value.to_str().ok())
+}
+
+#[inline]
+/// Whether an `If-Match` or `If-None-Match` list matches the entity tag, `*` matches any.
+/// The strong comparison fails for weak tags, the weak one compares only the opaque tags.
+fn tags_match(list: &str, etag: Option<&str>, strong: bool) -> bool {
+    if list.trim() == "*" {
+        return true;
+    }
+    let Some((weak, opaque)) = etag.and_then(|etag| entity_tags(etag).into_iter().next()) else {
+        return false;
+    };
+
+    entity_tags(list)
+        .into_iter()
+        .any(|(tag_weak, tag)| tag == opaque && !(strong && (weak || tag_weak)))
+}
+
+#[inline]
+/// Splits a list of entity tags into whether each one is weak and its opaque tag.
+/// Parsing stops at the first malformed tag.
+fn entity_tags(list: &str) -> Vec<(bool, &str)> {
+    let mut tags = Vec::new();
+    let mut rest = list;
+
+    loop {
+        rest = rest.trim_start_matches(|char: char| char == ',' || char.is_ascii_whitespace());
+        if rest.is_empty() {
+            return tags;
+        }
+
+        let (weak, tag) = match rest.strip_prefix("W/") {
+            Some(tag) => (true, tag),
+            None => (false, rest),
+        };
+        let Some((opaque, tail)) = tag.strip_prefix('"').and_then(|tag| tag.split_once('"')) else {
+            return tags;
+        };
+
+        tags.push((weak, opaque));
+        rest = tail;
+    }
+}
+
+#[inline]
+/// Seconds since the Unix epoch, 0 for earlier times.
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |dur| dur.as_secs())
+}
+
+#[inline]
+/// Formats a time as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let (year, month, day, hour, min, sec) = civil_time(time);
+    let weekday = WEEKDAYS[(unix_secs(time) / 86400 % 7) as usize];
+
+    format!(
+        "{weekday}, {day:02} {} {year} {hour:02}:{min:02}:{sec:02} GMT",
+        MONTHS[month as usize - 1]
+    )
+}
+
+#[inline]
+/// Parses an HTTP date in the IMF-fixdate, RFC 850 or asctime format,
+/// [None] if it is malformed.
+pub(crate) fn parse_http_date(text: &str) -> Option<SystemTime> {
+    let tokens = text
+        .split([' ', ',', '-', ':'])
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>();
+
+    let (day, month, year, hour, min, sec) = match tokens[..] {
+        [_, day, month, year, hour, min, sec, "GMT"] => (day, month, year, hour, min, sec),
+        [_, month, day, hour, min, sec, year] => (day, month, year, hour, min, sec),
+        _ => return None,
+    };
+
+    let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+    let year = match (number(year)? as i64, year.len()) {
+        (year, 2) if year < 70 => year + 2000,
+        (year, 2) => year + 1900,
+        (year, _) => year,
+    };
+    let (day, hour, min, sec) = (number(day)?, number(hour)?, number(min)?, number(sec)?);
+    if year > 9999 || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day as i64);
+    let secs = days * 86400 + (hour * 3600 + min * 60 + sec) as i64;
+    if secs < 0 {
+        return None;
+    }
+
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+#[inline]
+/// Number of days from the Unix epoch to a UTC date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
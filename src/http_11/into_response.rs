@@ -0,0 +1,201 @@
+use crate::*;
+
+/// Conversion of a value into a [Response].
+///
+/// Any type implementing this trait can be returned from the `work_fn` passed to [HttpServer].
+///
+/// # Examples
+/// ```
+/// use maker_web::{IntoResponse, Response};
+/// use http::StatusCode;
+///
+/// let response = (StatusCode::CREATED, "data").into_response();
+///
+/// assert_eq!(response.status_code, StatusCode::CREATED);
+/// assert_eq!(response.body.as_ref(), b"\r\ndata");
+/// ```
+/// Headers in a [HeaderMap] replace the ones set by the body:
+/// ```
+/// use maker_web::IntoResponse;
+/// use http::{header::CONTENT_TYPE, HeaderMap, HeaderValue};
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+/// let response = (headers, "<p>data</p>").into_response();
+///
+/// assert_eq!(response.headers.as_ref(), b"content-type: text/html\r\n");
+/// ```
+/// Returning a [Result] from a handler:
+/// ```no_run
+/// use maker_web::{HttpServer, Request};
+/// use tokio::net::TcpListener;
+/// use http::StatusCode;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::launch(TcpListener::bind("127.0.0.1:80").await.unwrap(), work).await;
+/// }
+///
+/// async fn work(request: Request) -> Result<String, (StatusCode, &'static str)> {
+///     match request.url.path() {
+///         "/" => Ok(String::from("All Good :)")),
+///         _ => Err((StatusCode::NOT_FOUND, "Page not found :(")),
+///     }
+/// }
+/// ```
+pub trait IntoResponse {
+    /// Converts the value into a [Response].
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    #[inline]
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+impl IntoResponse for StatusCode {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_response(self, "")
+    }
+}
+
+impl IntoResponse for () {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_response(StatusCode::OK, "")
+    }
+}
+
+impl IntoResponse for &'static str {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "text/plain; charset=utf-8");
+            resp.set_response(StatusCode::OK, self);
+        })
+    }
+}
+
+impl IntoResponse for String {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "text/plain; charset=utf-8");
+            resp.set_response(StatusCode::OK, self);
+        })
+    }
+}
+
+impl IntoResponse for &'static [u8] {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "application/octet-stream");
+            resp.set_response(StatusCode::OK, self);
+        })
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "application/octet-stream");
+            resp.set_response(StatusCode::OK, self);
+        })
+    }
+}
+
+impl IntoResponse for Bytes {
+    #[inline]
+    fn into_response(self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "application/octet-stream");
+            resp.set_response(StatusCode::OK, self);
+        })
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, T) {
+    #[inline]
+    fn into_response(self) -> Response {
+        let mut response = self.1.into_response();
+        response.status_code = self.0;
+        response
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (HeaderMap, T) {
+    #[inline]
+    /// Headers in the map replace the headers of the same name set by the body.
+    fn into_response(self) -> Response {
+        let mut response = self.1.into_response();
+        for name in self.0.keys() {
+            response.remove_header(name.as_str());
+        }
+        for (name, value) in self.0.iter() {
+            response.add_header(name, value);
+        }
+        response
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (StatusCode, HeaderMap, T) {
+    #[inline]
+    fn into_response(self) -> Response {
+        let mut response = (self.1, self.2).into_response();
+        response.status_code = self.0;
+        response
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    #[inline]
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => value.into_response(),
+            Err(err) => err.into_response(),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+/// JSON response body, serialized with `serde_json`.
+///
+/// # Examples
+/// ```
+/// use maker_web::{IntoResponse, Json};
+/// use http::StatusCode;
+///
+/// let response = Json(vec![1, 2, 3]).into_response();
+///
+/// assert_eq!(response.status_code, StatusCode::OK);
+/// assert_eq!(response.headers.as_ref(), b"Content-Type: application/json\r\n");
+/// assert_eq!(response.body.as_ref(), b"\r\n[1,2,3]");
+/// ```
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    #[inline]
+    fn into_response(self) -> Response {
+        match serde_json::to_vec(&self.0) {
+            Ok(data) => Response::from_fn(|resp| {
+                resp.add_header("Content-Type", "application/json");
+                resp.set_response(StatusCode::OK, data);
+            }),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl IntoResponse for serde_json::Value {
+    #[inline]
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
@@ -0,0 +1,342 @@
+use crate::*;
+
+/// Upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+/// Counters of [HttpServer], rendered in the Prometheus text format.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{HttpServer, Metrics, Request, Response};
+/// use once_cell::sync::Lazy;
+/// use std::sync::Arc;
+/// use tokio::net::TcpListener;
+///
+/// static METRICS: Lazy<Arc<Metrics>> = Lazy::new(Default::default);
+///
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::from_fn(|server| server.set_metrics(METRICS.clone()))
+///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+///         .await;
+/// }
+///
+/// async fn work(request: Request) -> Response {
+///     match request.url.path() {
+///         "/metrics" => METRICS.response(),
+///         _ => Response::from_body("All Good :)"),
+///     }
+/// }
+/// ```
+pub struct Metrics {
+    connections_accepted: AtomicU64,
+    connections_active: AtomicU64,
+    requests: DashMap<(&'static str, StatusCode), u64>,
+    request_bytes: AtomicU64,
+    response_bytes: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_sum_us: AtomicU64,
+    latency_count: AtomicU64,
+    errors: DashMap<&'static str, u64>,
+}
+
+/// Functions for recording [Metrics].
+impl Metrics {
+    #[inline]
+    /// Creating a new instance of [Metrics] with all counters at zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Metrics;
+    ///
+    /// let metrics = Metrics::new();
+    /// assert!(metrics.render().contains("maker_web_connections_accepted_total 0\n"));
+    /// ```
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(crate) fn connection_opened(&self) {
+        self.connections_accepted.fetch_add(1, Ordering::Relaxed);
+        self.connections_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn connection_closed(&self) {
+        self.connections_active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    /// Records one served request. Methods other than the standard ones are counted
+    /// as `OTHER`, so clients cannot add series.
+    ///
+    /// # Parameters
+    /// * `method` - HTTP request method.
+    /// * `status` - Response status code.
+    /// * `request_bytes` - Size of the received request.
+    /// * `response_bytes` - Size of the sent response.
+    /// * `latency` - Time from the parsed request to the written response.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Metrics;
+    /// use http::{Method, StatusCode};
+    /// use std::time::Duration;
+    ///
+    /// let metrics = Metrics::new();
+    /// metrics.record_request(&Method::GET, StatusCode::OK, 78, 120, Duration::from_millis(20));
+    ///
+    /// let text = metrics.render();
+    /// assert!(text.contains("maker_web_requests_total{method=\"GET\",status=\"200\"} 1\n"));
+    /// assert!(text.contains("maker_web_request_duration_seconds_bucket{le=\"0.01\"} 0\n"));
+    /// assert!(text.contains("maker_web_request_duration_seconds_bucket{le=\"0.025\"} 1\n"));
+    /// assert!(text.contains("maker_web_response_bytes_total 120\n"));
+    ///
+    /// let method = Method::from_bytes(b"PURGE").unwrap();
+    /// metrics.record_request(&method, StatusCode::OK, 78, 120, Duration::from_millis(20));
+    /// let text = metrics.render();
+    /// assert!(text.contains("maker_web_requests_total{method=\"OTHER\",status=\"200\"} 1\n"));
+    /// ```
+    pub fn record_request(
+        &self,
+        method: &Method,
+        status: StatusCode,
+        request_bytes: usize,
+        response_bytes: usize,
+        latency: Duration,
+    ) {
+        *self
+            .requests
+            .entry((method_label(method), status))
+            .or_insert(0) += 1;
+
+        self.request_bytes
+            .fetch_add(request_bytes as u64, Ordering::Relaxed);
+        self.response_bytes
+            .fetch_add(response_bytes as u64, Ordering::Relaxed);
+
+        let secs = latency.as_secs_f64();
+        if let Some(pos) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            self.latency_buckets[pos].fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_sum_us
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    /// Records one request answered with an error before it could be parsed,
+    /// counted with the method `-` and left out of the latency histogram.
+    pub(crate) fn record_rejected(&self, status: StatusCode, response_bytes: usize) {
+        *self.requests.entry(("-", status)).or_insert(0) += 1;
+
+        self.response_bytes
+            .fetch_add(response_bytes as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    /// Records one [ServerError], counted by its [ServerError::kind].
+    ///
+    /// # Parameters
+    /// * `err` - The error that occurred.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Metrics, ServerError};
+    ///
+    /// let metrics = Metrics::new();
+    /// metrics.record_error(&ServerError::BrokenFirstLine);
+    ///
+    /// assert!(metrics.render().contains("maker_web_errors_total{kind=\"BrokenFirstLine\"} 1\n"));
+    /// ```
+    pub fn record_error(&self, err: &ServerError) {
+        *self.errors.entry(err.kind()).or_insert(0) += 1;
+    }
+}
+
+/// Functions for exposing [Metrics].
+impl Metrics {
+    #[inline]
+    /// Renders all counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::with_capacity(2048);
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        Self::head(
+            &mut out,
+            "connections_accepted_total",
+            "counter",
+            "Accepted connections.",
+        );
+        out.push_str(&format!(
+            "maker_web_connections_accepted_total {}\n",
+            load(&self.connections_accepted)
+        ));
+
+        Self::head(
+            &mut out,
+            "connections_active",
+            "gauge",
+            "Currently open connections.",
+        );
+        out.push_str(&format!(
+            "maker_web_connections_active {}\n",
+            load(&self.connections_active)
+        ));
+
+        Self::head(&mut out, "requests_total", "counter", "Served requests.");
+        let mut requests: Vec<_> = self
+            .requests
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+        requests.sort();
+        for ((method, status), count) in requests {
+            out.push_str(&format!(
+                "maker_web_requests_total{{method=\"{method}\",status=\"{}\"}} {count}\n",
+                status.as_u16()
+            ));
+        }
+
+        Self::head(
+            &mut out,
+            "request_bytes_total",
+            "counter",
+            "Received request bytes, decoded sizes for HTTP/2.",
+        );
+        out.push_str(&format!(
+            "maker_web_request_bytes_total {}\n",
+            load(&self.request_bytes)
+        ));
+
+        Self::head(
+            &mut out,
+            "response_bytes_total",
+            "counter",
+            "Sent response bytes.",
+        );
+        out.push_str(&format!(
+            "maker_web_response_bytes_total {}\n",
+            load(&self.response_bytes)
+        ));
+
+        Self::head(
+            &mut out,
+            "request_duration_seconds",
+            "histogram",
+            "Time from the parsed request to the written response.",
+        );
+        let mut cumulative = 0;
+        for (le, bucket) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += load(bucket);
+            out.push_str(&format!(
+                "maker_web_request_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}\n"
+            ));
+        }
+        let count = load(&self.latency_count);
+        out.push_str(&format!(
+            "maker_web_request_duration_seconds_bucket{{le=\"+Inf\"}} {count}\n"
+        ));
+        out.push_str(&format!(
+            "maker_web_request_duration_seconds_sum {}\n",
+            load(&self.latency_sum_us) as f64 / 1_000_000.0
+        ));
+        out.push_str(&format!(
+            "maker_web_request_duration_seconds_count {count}\n"
+        ));
+
+        Self::head(
+            &mut out,
+            "errors_total",
+            "counter",
+            "Server errors by kind.",
+        );
+        let mut errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+        errors.sort();
+        for (kind, count) in errors {
+            out.push_str(&format!(
+                "maker_web_errors_total{{kind=\"{kind}\"}} {count}\n"
+            ));
+        }
+
+        out
+    }
+
+    #[inline]
+    /// Creates a [Response] with the rendered counters, to be returned from a metrics route.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Metrics;
+    /// use http::StatusCode;
+    ///
+    /// let response = Metrics::new().response();
+    ///
+    /// assert_eq!(response.status_code, StatusCode::OK);
+    /// assert_eq!(
+    ///     response.headers.as_ref(),
+    ///     b"Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n"
+    /// );
+    /// ```
+    pub fn response(&self) -> Response {
+        Response::from_fn(|resp| {
+            resp.add_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8");
+            resp.set_response(StatusCode::OK, self.render());
+        })
+    }
+
+    #[inline]
+    fn head(out: &mut String, name: &str, kind: &str, help: &str) {
+        out.push_str(&format!(
+            "# HELP maker_web_{name} {help}\n# TYPE maker_web_{name} {kind}\n"
+        ));
+    }
+}
+
+#[inline]
+/// Label of a request method, `OTHER` for extension methods.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+/// Closes the connection in [Metrics] when dropped, even if the task panics.
+pub(crate) struct ConnectionGuard(pub(crate) Option<Arc<Metrics>>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(metrics) = &self.0 {
+            metrics.connection_closed();
+        }
+    }
+}
+
+#[cfg(feature = "http2")]
+#[inline]
+/// Decoded size of an HTTP/2 request: method, URL, header names and values and the body.
+/// The frames on the wire are compressed with HPACK, so this is not the number of bytes read.
+pub(crate) fn decoded_size(request: &Request) -> usize {
+    let head = request.method.as_str().len() + request.url.to_string().len();
+
+    request.headers.iter().fold(head, |size, (name, value)| {
+        size + name.as_str().len() + value.len()
+    }) + request.body.len()
+}
@@ -0,0 +1,203 @@
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Maps URL paths to files inside a root directory without letting them leave it.
+///
+/// The path is percent-decoded and its `.` and `..` segments are resolved before it is joined
+/// to the root. Paths climbing above the root, NUL bytes, backslashes and, by default,
+/// symbolic links pointing out of the root are rejected with [ServerError::UnsafePath] (403).
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{PathResolver, Request, Response, ServerError};
+///
+/// async fn work(request: Request) -> Result<Response, ServerError> {
+///     let path = PathResolver::new("./public").resolve(request.url.path()).await?;
+///     Response::from_file(path, "text/html").await
+/// }
+/// ```
+pub struct PathResolver {
+    root: PathBuf,
+    symlink_escape: bool,
+}
+
+/// Functions for creating and configuring [PathResolver].
+impl PathResolver {
+    #[inline]
+    /// Creating a new instance of a [PathResolver], rejecting symbolic links out of the root.
+    ///
+    /// # Parameters
+    /// * `root` - Directory the resolved paths stay in.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::PathResolver;
+    ///
+    /// let resolver = PathResolver::new("./public");
+    /// ```
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        PathResolver {
+            root: root.as_ref().to_path_buf(),
+            symlink_escape: false,
+        }
+    }
+
+    #[inline]
+    /// Sets whether symbolic links inside the root may point out of it.
+    ///
+    /// # Parameters
+    /// * `symlink_escape` - Whether to follow such links instead of answering 403.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::PathResolver;
+    ///
+    /// let mut resolver = PathResolver::new("./public");
+    /// resolver.set_symlink_escape(true);
+    /// ```
+    pub fn set_symlink_escape(&mut self, symlink_escape: bool) {
+        self.symlink_escape = symlink_escape;
+    }
+
+    #[inline]
+    /// Directory the resolved paths stay in.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+/// Functions for resolving paths with [PathResolver].
+impl PathResolver {
+    #[inline]
+    /// Resolves a URL path to a path inside the root. Unless symbolic links may escape,
+    /// the path has to exist, missing files return [ServerError::OpeningFile] (404).
+    ///
+    /// # Parameters
+    /// * `url_path` - Percent-encoded path, e.g. from `request.url.path()`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{PathResolver, ServerError};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let name = format!("maker_web_path_resolver_{}", std::process::id());
+    ///     let root = std::env::temp_dir().join(name);
+    ///     std::fs::create_dir_all(root.join("docs")).unwrap();
+    ///     std::fs::write(root.join("docs/read me.txt"), "Hello").unwrap();
+    ///
+    ///     let resolver = PathResolver::new(&root);
+    ///     assert_eq!(
+    ///         resolver.resolve("/docs/./read%20me.txt").await.unwrap(),
+    ///         root.join("docs/read me.txt")
+    ///     );
+    ///     assert!(matches!(
+    ///         resolver.resolve("/docs/%2e%2e/%2e%2e/etc/passwd").await,
+    ///         Err(ServerError::UnsafePath(_))
+    ///     ));
+    ///     std::fs::remove_dir_all(&root).unwrap();
+    /// }
+    /// ```
+    pub async fn resolve(&self, url_path: &str) -> Result<PathBuf, ServerError> {
+        let path = self.root.join(Self::normalize(url_path)?);
+        if self.symlink_escape {
+            return Ok(path);
+        }
+
+        let root = fs::canonicalize(&self.root)
+            .await
+            .map_err(ServerError::OpeningFile)?;
+        let target = fs::canonicalize(&path)
+            .await
+            .map_err(ServerError::OpeningFile)?;
+        if !target.starts_with(&root) {
+            return Err(ServerError::UnsafePath(
+                "symbolic link leads out of the root",
+            ));
+        }
+
+        Ok(path)
+    }
+
+    #[inline]
+    /// Percent-decodes a URL path and resolves its `.` and `..` segments
+    /// into a relative path, without touching the file system.
+    ///
+    /// # Parameters
+    /// * `url_path` - Percent-encoded path, e.g. from `request.url.path()`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{PathResolver, ServerError};
+    /// use std::path::PathBuf;
+    ///
+    /// assert_eq!(
+    ///     PathResolver::normalize("/a/./b/../c%20d").unwrap(),
+    ///     PathBuf::from("a/c d")
+    /// );
+    /// assert_eq!(PathResolver::normalize("/").unwrap(), PathBuf::new());
+    ///
+    /// for path in ["/../secret", "/a/%2E%2E/%2e%2e/secret", "/a%00.txt", "/a%5c..", "/%zz"] {
+    ///     assert!(matches!(PathResolver::normalize(path), Err(ServerError::UnsafePath(_))));
+    /// }
+    /// ```
+    pub fn normalize(url_path: &str) -> Result<PathBuf, ServerError> {
+        let decoded =
+            percent_decode(url_path).ok_or(ServerError::UnsafePath("invalid percent-encoding"))?;
+        let decoded =
+            String::from_utf8(decoded).map_err(|_| ServerError::UnsafePath("invalid UTF-8"))?;
+
+        if decoded.contains('\0') {
+            return Err(ServerError::UnsafePath("NUL byte"));
+        }
+        if decoded.contains('\\') {
+            return Err(ServerError::UnsafePath("backslash"));
+        }
+
+        let mut segments = Vec::new();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if segments.pop().is_none() {
+                        return Err(ServerError::UnsafePath("path leads out of the root"));
+                    }
+                }
+                #[cfg(windows)]
+                segment if segment.contains(':') => {
+                    return Err(ServerError::UnsafePath("drive or stream name"));
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        Ok(segments.into_iter().collect())
+    }
+}
+
+#[inline]
+/// Decodes `%XX` escapes, [None] if an escape is not two hex digits.
+fn percent_decode(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = bytes.get(index + 1..index + 3)?;
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+                index += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    Some(decoded)
+}
@@ -0,0 +1,233 @@
+use crate::http_11::stream::{BodyStream, Segment};
+use crate::shared::Shared;
+use crate::*;
+
+/// Most ranges served for one request, more are answered with the whole file.
+const MAX_RANGES: usize = 32;
+
+/// Counter making the `multipart/byteranges` boundaries unique.
+static BOUNDARY: AtomicU64 = AtomicU64::new(0);
+
+/// `Range` and `If-Range` headers of a request, applied to the file response of the handler.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RangeRequest {
+    range: Option<HeaderValue>,
+    if_range: Option<HeaderValue>,
+}
+
+/// Functions for creating and applying [RangeRequest].
+impl RangeRequest {
+    #[inline]
+    /// Takes the headers of the request, ranges are served only for `GET`.
+    ///
+    /// # Parameters
+    /// * `request` - Request passed to the handler.
+    pub(crate) fn from_request(request: &Request) -> Self {
+        if request.method != Method::GET {
+            return Self::default();
+        }
+
+        RangeRequest {
+            range: request.headers.get(RANGE).cloned(),
+            if_range: request.headers.get(IF_RANGE).cloned(),
+        }
+    }
+
+    #[inline]
+    /// Advertises byte ranges on a 200 response with a whole file and answers the requested
+    /// ranges with 206, or with 416 if none of them is in the file. A `Range` header
+    /// that cannot be parsed or lists too many ranges is ignored.
+    ///
+    /// # Parameters
+    /// * `response` - Response of the handler.
+    pub(crate) fn apply(&self, response: &mut Response) {
+        if response.status_code != StatusCode::OK {
+            return;
+        }
+        let Some(size) = response
+            .stream
+            .as_ref()
+            .and_then(|stream| stream.map(BodyStream::file_size))
+            .flatten()
+        else {
+            return;
+        };
+        response.add_header("Accept-Ranges", "bytes");
+
+        let Some(range) = &self.range else {
+            return;
+        };
+        if !self.if_range_matches(response) {
+            return;
+        }
+        let Some(ranges) = range.to_str().ok().and_then(|range| parse(range, size)) else {
+            return;
+        };
+
+        if ranges.is_empty() {
+            response.status_code = StatusCode::RANGE_NOT_SATISFIABLE;
+            response.stream = None;
+            response.remove_header("Content-Type");
+            response.add_header("Content-Range", format!("bytes */{size}"));
+            return;
+        }
+
+        let Some(BodyStream::File { file, position, .. }) =
+            response.stream.take().as_ref().and_then(Shared::take)
+        else {
+            return;
+        };
+
+        let segments = match ranges[..] {
+            [(start, end)] => {
+                response.add_header("Content-Range", format!("bytes {start}-{end}/{size}"));
+                VecDeque::from([Segment::Range {
+                    offset: start,
+                    length: end - start + 1,
+                }])
+            }
+            _ => {
+                let content_type = response.header("Content-Type").map(str::to_string);
+                response.remove_header("Content-Type");
+
+                let boundary = Self::boundary();
+                response.add_header(
+                    "Content-Type",
+                    format!("multipart/byteranges; boundary={boundary}"),
+                );
+
+                Self::multipart(&ranges, size, &boundary, content_type.as_deref())
+            }
+        };
+
+        response.status_code = StatusCode::PARTIAL_CONTENT;
+        response.stream = Some(Shared::new(BodyStream::file_segments(
+            file, position, segments,
+        )));
+    }
+
+    #[inline]
+    /// Whether the ranges may be served: without `If-Range`, or if it equals the strong
+    /// `ETag` or the `Last-Modified` date of the response.
+    fn if_range_matches(&self, response: &Response) -> bool {
+        let Some(if_range) = &self.if_range else {
+            return true;
+        };
+        let Ok(if_range) = if_range.to_str().map(str::trim) else {
+            return false;
+        };
+
+        match if_range.starts_with('"') {
+            true => response.header("ETag") == Some(if_range),
+            false if if_range.starts_with("W/") => false,
+            false => response.header("Last-Modified") == Some(if_range),
+        }
+    }
+
+    #[inline]
+    /// Part headers and file ranges of a `multipart/byteranges` body.
+    fn multipart(
+        ranges: &[(u64, u64)],
+        size: u64,
+        boundary: &str,
+        content_type: Option<&str>,
+    ) -> VecDeque<Segment> {
+        let mut segments = VecDeque::with_capacity(ranges.len() * 2 + 1);
+
+        for &(start, end) in ranges {
+            let mut head = format!("\r\n--{boundary}\r\n");
+            if let Some(content_type) = content_type {
+                head.push_str(&format!("Content-Type: {content_type}\r\n"));
+            }
+            head.push_str(&format!(
+                "Content-Range: bytes {start}-{end}/{size}\r\n\r\n"
+            ));
+
+            segments.push_back(Segment::Bytes(Bytes::from(head)));
+            segments.push_back(Segment::Range {
+                offset: start,
+                length: end - start + 1,
+            });
+        }
+        segments.push_back(Segment::Bytes(Bytes::from(format!(
+            "\r\n--{boundary}--\r\n"
+        ))));
+
+        segments
+    }
+
+    #[inline]
+    /// Boundary unlikely to appear in the file.
+    fn boundary() -> String {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let count = BOUNDARY.fetch_add(1, Ordering::Relaxed);
+
+        format!("{nanos:016x}{count:08x}")
+    }
+}
+
+#[inline]
+/// Parses a `bytes` range header into sorted inclusive ranges within a file of `size` bytes,
+/// overlapping and adjacent ranges are merged. Returns [None] if the header is invalid
+/// or lists more than [MAX_RANGES] ranges, and no ranges if none of them is satisfiable.
+fn parse(range: &str, size: u64) -> Option<Vec<(u64, u64)>> {
+    let (unit, specs) = range.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let specs = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect::<Vec<_>>();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = match first.is_empty() {
+            true => {
+                let suffix = number(last)?;
+                (suffix > 0 && size > 0).then(|| (size.saturating_sub(suffix), size - 1))
+            }
+            false => {
+                let start = number(first)?;
+                let end = match last.is_empty() {
+                    true => u64::MAX,
+                    false => number(last).filter(|end| *end >= start)?,
+                };
+                (start < size).then(|| (start, end.min(size - 1)))
+            }
+        };
+
+        ranges.extend(range);
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    Some(merged)
+}
+
+#[inline]
+/// Parses a number of decimal digits only.
+pub(crate) fn number(text: &str) -> Option<u64> {
+    match !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
@@ -1,122 +1,415 @@
+use crate::http_11::conditional::ConditionalRequest;
+use crate::http_11::range::number;
+use crate::http_11::server::with_timeout;
 use crate::*;
 
+/// Longest chunk-size line of a chunked body, with its extensions and line ending.
+const MAX_CHUNK_LINE: usize = 1024;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// Represents a parsed HTTP request, containing method, URL, headers, body, and more.
 /// Includes fields for host, cookies, and an optional socket address.
 pub struct Request {
-    /// Client socket address.
+    #[cfg(feature = "get_stream")]
+    /// Client socket address, only with the `get_stream` feature.
     pub socket_addr: SocketAddr,
     /// HTTP request method (GET, POST, etc.).
     pub method: Method,
     /// Requested URL path.
     pub url: Uri,
+    /// HTTP version of the request, the response is sent with the same version.
+    /// HTTP/1.0 and HTTP/1.1 are accepted, other versions are answered with 505.
+    pub version: Version,
     /// Request cookies as key-value pairs.
     pub cookies: HashMap<String, String>,
     /// HTTP request headers as key-value pairs.
     pub headers: HeaderMap,
-    /// Request body as a byte vector.
+    /// Request body as a byte vector, a chunked body is decoded.
     pub body: Vec<u8>,
+    #[cfg(feature = "tls")]
+    /// TLS session of the connection, [None] on plain TCP. Only with the `tls` feature.
+    pub tls: Option<TlsInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Size limits enforced while reading a [Request].
+pub struct Limits {
+    /// Maximum length of the request line in bytes, exceeding it answers 414.
+    pub request_line: usize,
+    /// Maximum total size of all header lines in bytes, exceeding it answers 431.
+    pub header_bytes: usize,
+    /// Maximum number of headers, exceeding it answers 431.
+    pub header_count: usize,
+    /// Maximum body size in bytes, exceeding it answers 413.
+    pub body: usize,
+    /// Maximum number of concurrent HTTP/2 streams per connection, advertised to the client.
+    /// Streams opened beyond it are refused and not handled.
+    pub streams: u32,
+}
+
+impl Default for Limits {
+    /// 8 KiB request line, 64 KiB of headers, 100 headers, 2 MiB body and 100 HTTP/2 streams.
+    fn default() -> Self {
+        Limits {
+            request_line: 8 * 1024,
+            header_bytes: 64 * 1024,
+            header_count: 100,
+            body: 2 * 1024 * 1024,
+            streams: 100,
+        }
+    }
+}
+
+/// Functions for reading [Request] properties.
+impl Request {
+    #[inline]
+    /// Whether the client allows the connection to stay open after the response.
+    /// HTTP/1.1 connections stay open unless the `Connection` header contains `close`,
+    /// HTTP/1.0 connections only if it contains `keep-alive`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Request, Response};
+    ///
+    /// async fn work(request: Request) -> Response {
+    ///     let mut response = Response::from_body("All Good :)");
+    ///     if !request.keep_alive() {
+    ///         response.add_header("Connection", "close");
+    ///     }
+    ///     response
+    /// }
+    /// ```
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |name: &str| {
+            self.headers
+                .get_all(CONNECTION)
+                .iter()
+                .filter_map(|val| val.to_str().ok())
+                .flat_map(|val| val.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case(name))
+        };
+
+        match self.version {
+            Version::HTTP_09 | Version::HTTP_10 => has_token("keep-alive"),
+            _ => !has_token("close"),
+        }
+    }
+
+    #[inline]
+    /// ID of the last Server-Sent Event the client received, sent in the `Last-Event-ID` header
+    /// when an `EventSource` reconnects. See [Response::from_events].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Request, Response};
+    ///
+    /// async fn work(request: Request) -> Response {
+    ///     match request.last_event_id() {
+    ///         Some(id) => Response::from_body(format!("Resuming after {id}")),
+    ///         None => Response::from_body("Starting"),
+    ///     }
+    /// }
+    /// ```
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.headers
+            .get("last-event-id")
+            .and_then(|val| val.to_str().ok())
+    }
+
+    #[inline]
+    /// Evaluates the `If-Match`, `If-Unmodified-Since`, `If-None-Match` and
+    /// `If-Modified-Since` headers against the current state of an existing resource.
+    /// Returns the status to answer with instead of performing the request: 412 Precondition
+    /// Failed, or 304 Not Modified for `GET` and `HEAD`. The server already does this
+    /// for `GET` and `HEAD` responses with [Response::set_etag] or [Response::set_last_modified],
+    /// handlers changing a resource call it before applying the change.
+    ///
+    /// # Parameters
+    /// * `etag` - Current entity tag as sent in `ETag`, e.g. `"v1"` or `W/"v1"`.
+    /// * `last_modified` - Current modification time.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Request, Response};
+    ///
+    /// async fn work(request: Request) -> Response {
+    ///     if let Some(status) = request.evaluate_preconditions(Some("\"v2\""), None) {
+    ///         return Response::from_response(status, "");
+    ///     }
+    ///     Response::from_body("Updated")
+    /// }
+    /// ```
+    pub fn evaluate_preconditions(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Option<StatusCode> {
+        ConditionalRequest::from_request(self).evaluate(etag, last_modified)
+    }
 }
 
 /// Functions for creating [Request].
 impl Request {
     #[inline]
-    pub(crate) async fn result_from(
-        read_half: &mut ReadHalf<TcpStream>,
+    /// Reads and parses one request, also returns the number of bytes it took.
+    /// An error comes with the version of the request line, if it was read before.
+    pub(crate) async fn result_from<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
         adder: SocketAddr,
+        limits: &Limits,
+        timeouts: &Timeouts,
+    ) -> Result<(Request, usize), (ServerError, Option<Version>)> {
+        let reader = &mut Counted { reader, count: 0 };
+        let mut version = None;
+
+        let head = Self::read_head(reader, adder, limits, &mut version);
+        let mut request = with_timeout(timeouts.head, head, ServerError::HeadTimeout)
+            .await
+            .and_then(|head| head)
+            .map_err(|err| (err, version))?;
+
+        let body = Self::read_body(reader, &request.headers, limits);
+        request.body = with_timeout(timeouts.body, body, ServerError::BodyTimeout)
+            .await
+            .and_then(|body| body)
+            .map_err(|err| (err, Some(request.version)))?;
+
+        request.parse_cookies();
+
+        Ok((request, reader.count))
+    }
+
+    #[inline]
+    /// Fills `cookies` from the `Cookie` header.
+    pub(crate) fn parse_cookies(&mut self) {
+        if let Some(cookies) = self.headers.get(COOKIE).and_then(|val| val.to_str().ok()) {
+            self.cookies = Self::parse_query_string(cookies, '&');
+        }
+    }
+
+    #[inline]
+    /// Reads the request line and headers, `parsed_version` is set once the request line is read.
+    async fn read_head<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        adder: SocketAddr,
+        limits: &Limits,
+        parsed_version: &mut Option<Version>,
     ) -> Result<Request, ServerError> {
-        let mut reader = BufReader::new(read_half);
         let mut request_line = String::with_capacity(100);
 
-        if reader
-            .read_line(&mut request_line)
-            .await
-            .map_err(ServerError::Read)?
-            == 0
-        {
+        let too_long = ServerError::RequestLineTooLong;
+        let len = Self::read_limited_line(reader, &mut request_line, limits.request_line, too_long);
+        if len.await? == 0 {
             return Err(ServerError::EmptyRequest);
         }
 
         let mut parts = request_line.split_whitespace();
-        let (Some(method), Some(url), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        let (Some(method), Some(url), Some(version)) = (parts.next(), parts.next(), parts.next())
+        else {
             return Err(ServerError::BrokenFirstLine);
         };
+        let version = match version {
+            "HTTP/1.1" => Version::HTTP_11,
+            "HTTP/1.0" => Version::HTTP_10,
+            version if version.starts_with("HTTP/") => {
+                return Err(ServerError::UnsupportedVersion(version.to_string()))
+            }
+            _ => return Err(ServerError::BrokenFirstLine),
+        };
+        *parsed_version = Some(version);
+
+        #[cfg(not(feature = "get_stream"))]
+        let _ = adder;
 
         let mut request = Request {
+            #[cfg(feature = "get_stream")]
             socket_addr: adder,
             method: Method::from_str(method).map_err(ServerError::InvalidMethod)?,
             url: Uri::from_str(url).map_err(ServerError::InvalidUrl)?,
+            version,
 
             cookies: HashMap::new(),
             headers: HeaderMap::with_capacity(20),
             body: Vec::new(),
+            #[cfg(feature = "tls")]
+            tls: None,
         };
 
         let mut header_line = String::with_capacity(150);
+        let (mut header_bytes, mut header_count) = (0, 0);
         loop {
-            match Self::read_header_line(&mut reader, &mut header_line, &mut request.headers).await
+            let budget = limits.header_bytes.saturating_sub(header_bytes);
+            match Self::read_header_line(reader, &mut header_line, &mut request.headers, budget)
+                .await
             {
-                Ok(_) => continue,
+                Ok(len) => header_bytes += len,
                 Err(ServerError::EmptyLine) => break,
                 Err(e) => return Err(e),
             }
+
+            header_count += 1;
+            if header_count > limits.header_count {
+                return Err(ServerError::TooManyHeaders);
+            }
         }
 
-        if let Some(length) = request
-            .headers
-            .get(CONTENT_LENGTH)
-            .and_then(|val| val.to_str().ok())
-            .and_then(|val| val.parse::<usize>().ok())
-        {
-            let mut body = vec![0; length];
-            if reader.read_exact(&mut body).await.is_ok() {
-                request.body = body;
+        Ok(request)
+    }
+
+    #[inline]
+    /// Reads the body framed by `Content-Length` or `Transfer-Encoding: chunked`,
+    /// a request with neither has no body.
+    async fn read_body<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        headers: &HeaderMap,
+        limits: &Limits,
+    ) -> Result<Vec<u8>, ServerError> {
+        let length = headers.get(CONTENT_LENGTH);
+
+        let Some(coding) = headers.get(TRANSFER_ENCODING) else {
+            let Some(length) = length else {
+                return Ok(Vec::new());
+            };
+            let length = length
+                .to_str()
+                .ok()
+                .and_then(number)
+                .ok_or(ServerError::InvalidContentLength)?;
+            if length > limits.body as u64 {
+                return Err(ServerError::BodyTooLarge);
             }
+
+            let mut body = vec![0; length as usize];
+            reader
+                .read_exact(&mut body)
+                .await
+                .map_err(ServerError::Read)?;
+            return Ok(body);
+        };
+
+        if length.is_some() {
+            return Err(ServerError::InvalidContentLength);
+        }
+        if !coding.as_bytes().eq_ignore_ascii_case(b"chunked") {
+            return Err(ServerError::UnsupportedTransferEncoding);
         }
 
-        if let Some(cookies) = request
-            .headers
-            .get(COOKIE)
-            .and_then(|val| val.to_str().ok())
-        {
-            request.cookies = Self::parse_query_string(cookies, '&');
+        Self::read_chunked(reader, limits).await
+    }
+
+    #[inline]
+    /// Decodes a chunked body of at most [Limits::body] bytes, trailers are dropped.
+    async fn read_chunked<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        limits: &Limits,
+    ) -> Result<Vec<u8>, ServerError> {
+        let mut body = Vec::new();
+        let mut line = String::with_capacity(20);
+
+        loop {
+            line.clear();
+            let too_long = ServerError::InvalidChunk;
+            Self::read_limited_line(reader, &mut line, MAX_CHUNK_LINE, too_long).await?;
+
+            let size = line.split(';').next().map_or("", str::trim);
+            if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(ServerError::InvalidChunk);
+            }
+            let size = u64::from_str_radix(size, 16).map_err(|_| ServerError::InvalidChunk)?;
+            if size == 0 {
+                break;
+            }
+            if size > (limits.body - body.len()) as u64 {
+                return Err(ServerError::BodyTooLarge);
+            }
+
+            let start = body.len();
+            body.resize(start + size as usize, 0);
+            reader
+                .read_exact(&mut body[start..])
+                .await
+                .map_err(ServerError::Read)?;
+
+            line.clear();
+            Self::read_limited_line(reader, &mut line, 2, ServerError::InvalidChunk).await?;
+            if line != "\r\n" && line != "\n" {
+                return Err(ServerError::InvalidChunk);
+            }
         }
 
-        Ok(request)
+        let mut trailer_bytes = 0;
+        loop {
+            line.clear();
+            let budget = limits.header_bytes.saturating_sub(trailer_bytes);
+            let too_long = ServerError::HeadersTooLarge;
+            let len = Self::read_limited_line(reader, &mut line, budget.max(2), too_long).await?;
+
+            if len == 0 {
+                return Err(ServerError::InvalidChunk);
+            }
+            if line.trim_end().is_empty() {
+                return Ok(body);
+            }
+            trailer_bytes += len;
+            if trailer_bytes > limits.header_bytes {
+                return Err(ServerError::HeadersTooLarge);
+            }
+        }
     }
 
     #[inline]
-    async fn read_header_line(
-        reader: &mut BufReader<&mut ReadHalf<TcpStream>>,
+    async fn read_header_line<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
         header_line: &mut String,
         headers: &mut HeaderMap,
-    ) -> Result<(), ServerError> {
+        max_len: usize,
+    ) -> Result<usize, ServerError> {
         header_line.clear();
 
-        if reader
-            .read_line(header_line)
-            .await
-            .map_err(ServerError::Read)?
-            == 0
-            || header_line.trim_end().is_empty()
-        {
+        // The empty line ending the headers is let through even when the budget is used up.
+        let too_long = ServerError::HeadersTooLarge;
+        let len = Self::read_limited_line(reader, header_line, max_len.max(2), too_long).await?;
+        if len == 0 || header_line.trim_end().is_empty() {
             return Err(ServerError::EmptyLine);
         }
+        if len > max_len {
+            return Err(ServerError::HeadersTooLarge);
+        }
 
         let mut parts = header_line.trim().splitn(2, ':');
 
-        let name = parts
-            .next()
-            .ok_or_else(|| ServerError::InvalidHeader)?
-            .trim();
-        let value = parts
-            .next()
-            .ok_or_else(|| ServerError::InvalidHeader)?
-            .trim();
+        let name = parts.next().ok_or(ServerError::InvalidHeader)?.trim();
+        let value = parts.next().ok_or(ServerError::InvalidHeader)?.trim();
 
-        headers.insert(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        let (name, value) = (HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        if name == CONTENT_LENGTH && headers.get(CONTENT_LENGTH).is_some_and(|old| *old != value) {
+            return Err(ServerError::InvalidContentLength);
+        }
+        headers.insert(name, value);
 
-        Ok(())
+        Ok(len)
+    }
+
+    #[inline]
+    /// Reads one line of at most `max_len` bytes, returns `too_long` if the
+    /// line does not end within the limit.
+    async fn read_limited_line<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        line: &mut String,
+        max_len: usize,
+        too_long: ServerError,
+    ) -> Result<usize, ServerError> {
+        let len = reader
+            .take(max_len as u64 + 1)
+            .read_line(line)
+            .await
+            .map_err(ServerError::Read)?;
+
+        if len > max_len && !line.ends_with('\n') {
+            return Err(too_long);
+        }
+
+        Ok(len)
     }
 
     #[inline]
@@ -154,3 +447,35 @@ impl Request {
             .collect()
     }
 }
+
+/// Reader counting the bytes of a request as the parser consumes them,
+/// bytes buffered for the next request are not counted.
+struct Counted<'a, R> {
+    reader: &'a mut R,
+    count: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for Counted<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut *self.reader).poll_read(cx, buf);
+
+        self.count += buf.filled().len() - filled;
+        poll
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for Counted<'_, R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        Pin::new(&mut *self.get_mut().reader).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.count += amt;
+        Pin::new(&mut *self.reader).consume(amt);
+    }
+}
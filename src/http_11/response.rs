@@ -1,7 +1,25 @@
+use crate::http_11::conditional::http_date;
+use crate::http_11::stream::BodyStream;
+use crate::shared::Shared;
+use crate::upgrade::OnUpgrade;
 use crate::*;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone)]
 /// Response.
+///
+/// Created with [Response::new] or the other constructors, the struct has private fields
+/// for streamed bodies and upgrades and cannot be built with a literal. Responses are
+/// compared, ordered and hashed by the status code, body, cookies and headers only.
+///
+/// # Examples
+/// ```
+/// use maker_web::Response;
+/// use std::collections::HashSet;
+///
+/// let set: HashSet<_> = [Response::from_body("a"), Response::from_body("a")].into();
+/// assert_eq!(set.len(), 1);
+/// assert!(Response::from_body("a") < Response::from_body("b"));
+/// ```
 pub struct Response {
     /// HTTP status code.
     pub status_code: StatusCode,
@@ -11,6 +29,10 @@ pub struct Response {
     pub cookies: BytesMut,
     /// Response Headers.
     pub headers: BytesMut,
+    /// Callback taking over the connection after the response.
+    pub(crate) upgrade: Option<OnUpgrade>,
+    /// Body sent in chunks after the response head.
+    pub(crate) stream: Option<Shared<BodyStream>>,
 }
 
 impl Default for Response {
@@ -19,10 +41,48 @@ impl Default for Response {
     }
 }
 
+// The manual PartialEq, Ord and Hash below go through the public fields only. The private
+// `upgrade` and `stream` fields are deliberately excluded, they hold a callback and a body
+// stream that have no value to compare and are shared by the clones of a response.
+impl Response {
+    #[inline]
+    fn key(&self) -> (&StatusCode, &BytesMut, &BytesMut, &BytesMut) {
+        (&self.status_code, &self.body, &self.cookies, &self.headers)
+    }
+}
+
+impl PartialEq for Response {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Response {}
+
+impl PartialOrd for Response {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Response {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl std::hash::Hash for Response {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
 /// Functions for formatting [Response].
 impl Response {
     #[inline]
-    /// Translation of [Response] into byte code.
+    /// Translation of [Response] into byte code. A response with a streamed body,
+    /// e.g. from [Response::set_file] or [Response::set_stream], returns
+    /// [ServerError::StreamedBody], so it cannot be a page of [set_def_pages].
     ///
     /// # Examples
     /// ```
@@ -41,20 +101,19 @@ impl Response {
     /// assert_eq!(response.as_bytes().unwrap(), Bytes::from(data));
     /// ```
     pub fn as_bytes(&self) -> Result<Bytes, ServerError> {
-        let mut binding = itoa::Buffer::new();
-        let status_code = binding.format(self.status_code.as_u16()).as_bytes();
-        let reason = self
-            .status_code
-            .canonical_reason()
-            .ok_or_else(|| ServerError::UnknownHttpStatus(self.status_code.as_u16()))?
-            .as_bytes();
+        if self.stream.is_some() {
+            return Err(ServerError::StreamedBody);
+        }
 
-        let mut bytes = BytesMut::with_capacity(
-            15 + reason.len() + self.cookies.len() + self.headers.len() + self.body.len(),
-        );
+        let reason = self.reason()?;
+        let mut bytes = BytesMut::with_capacity(self.wire_len());
 
         bytes.extend_from_slice(b"HTTP/1.1 ");
-        bytes.extend_from_slice(status_code);
+        bytes.extend_from_slice(
+            itoa::Buffer::new()
+                .format(self.status_code.as_u16())
+                .as_bytes(),
+        );
         bytes.extend_from_slice(b" ");
         bytes.extend_from_slice(reason);
         bytes.extend_from_slice(b"\r\n");
@@ -65,6 +124,231 @@ impl Response {
 
         Ok(bytes.freeze())
     }
+
+    #[inline]
+    /// Reason phrase of the status code.
+    fn reason(&self) -> Result<&'static [u8], ServerError> {
+        self.status_code
+            .canonical_reason()
+            .map(str::as_bytes)
+            .ok_or_else(|| ServerError::UnknownHttpStatus(self.status_code.as_u16()))
+    }
+
+    #[inline]
+    /// Size of the response as returned by [Response::as_bytes].
+    pub(crate) fn wire_len(&self) -> usize {
+        let reason = self.status_code.canonical_reason().map_or(0, str::len);
+        15 + reason + self.cookies.len() + self.headers.len() + self.body.len()
+    }
+
+    #[inline]
+    /// Splits the response into its header lines and the body content. The lines at the
+    /// start of `body` before the empty line, e.g. the `Location` of a redirect, belong
+    /// to the head.
+    pub(crate) fn parts(&self) -> ([&[u8]; 3], &[u8]) {
+        let (extra, content) = match self.body.strip_prefix(b"\r\n") {
+            Some(content) => (&[][..], content),
+            None => match self
+                .body
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+            {
+                Some(pos) => (&self.body[..pos + 2], &self.body[pos + 4..]),
+                None => (&self.body[..], &[][..]),
+            },
+        };
+
+        ([&self.cookies, &self.headers, extra], content)
+    }
+}
+
+/// How the body of a [Response] is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    /// The body is sent with the head.
+    Full,
+    /// The body is streamed after the head, with the length if it is known.
+    Stream(Option<u64>),
+    /// No body, the connection is handed over after the head.
+    Upgrade,
+}
+
+/// A serialized [Response] ready to be sent over a connection.
+pub(crate) struct Framed {
+    /// Bytes to write.
+    pub(crate) bytes: Bytes,
+    /// Size of the body in bytes.
+    pub(crate) body: usize,
+    /// Whether the connection can be reused after this response.
+    pub(crate) keep_alive: bool,
+    /// How the body is delimited.
+    pub(crate) framing: Framing,
+}
+
+#[inline]
+/// Splits a header line into its name and value, without the surrounding whitespace.
+pub(crate) fn header_line(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let pos = line.iter().position(|byte| *byte == b':')?;
+    Some((line[..pos].trim_ascii(), line[pos + 1..].trim_ascii()))
+}
+
+/// Functions for sending [Response] over a connection.
+impl Response {
+    #[inline]
+    /// Serializes the response for the request version: the status line gets the version,
+    /// the body a `Content-Length` header (except for 1xx, 204 and 304) and the connection
+    /// a `Connection` header if it differs from the version default. An upgrade response gets
+    /// neither, the connection is handed over after it. A `Transfer-Encoding: chunked` body
+    /// is kept as is for HTTP/1.1 and decoded for HTTP/1.0, which has no transfer codings,
+    /// a body that is not valid chunked coding is replaced by an empty 500 closing the connection.
+    /// A streamed body of unknown length is chunked for HTTP/1.1 and ends with the connection
+    /// for HTTP/1.0. The answer to a `HEAD` request keeps the headers of the body but not the body.
+    pub(crate) fn frame(
+        &self,
+        version: Version,
+        keep_alive: bool,
+        framing: Framing,
+        head_request: bool,
+    ) -> Result<Framed, ServerError> {
+        let reason = self.reason()?;
+        let (head, body) = self.parts();
+        let http_10 = version == Version::HTTP_10;
+
+        let mut framed = BytesMut::with_capacity(self.wire_len() + 45);
+        let (mut has_length, mut chunked) = (false, false);
+        let (mut has_connection, mut close) = (false, false);
+        let (stream, length) = match framing {
+            Framing::Stream(length) => (true, length),
+            _ => (false, None),
+        };
+        let no_body = framing != Framing::Full
+            || self.status_code.is_informational()
+            || self.status_code == StatusCode::NO_CONTENT
+            || self.status_code == StatusCode::NOT_MODIFIED;
+
+        framed.extend_from_slice(if http_10 { b"HTTP/1.0 " } else { b"HTTP/1.1 " });
+        framed.extend_from_slice(
+            itoa::Buffer::new()
+                .format(self.status_code.as_u16())
+                .as_bytes(),
+        );
+        framed.extend_from_slice(b" ");
+        framed.extend_from_slice(reason);
+        framed.extend_from_slice(b"\r\n");
+
+        for line in head
+            .iter()
+            .flat_map(|part| part.split_inclusive(|byte| *byte == b'\n'))
+        {
+            if let Some((name, value)) = header_line(line) {
+                if name.eq_ignore_ascii_case(b"content-length") {
+                    if stream {
+                        continue;
+                    }
+                    has_length = true;
+                } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+                    if stream {
+                        continue;
+                    }
+                    chunked = value
+                        .rsplit(|byte| *byte == b',')
+                        .next()
+                        .is_some_and(|coding| coding.trim_ascii().eq_ignore_ascii_case(b"chunked"));
+                    if http_10 {
+                        continue;
+                    }
+                } else if name.eq_ignore_ascii_case(b"connection") {
+                    has_connection = true;
+                    close |= value
+                        .split(|byte| *byte == b',')
+                        .any(|token| token.trim_ascii().eq_ignore_ascii_case(b"close"));
+                }
+            }
+
+            framed.extend_from_slice(line);
+            if !line.ends_with(b"\n") {
+                framed.extend_from_slice(b"\r\n");
+            }
+        }
+
+        let decoded = match chunked && http_10 {
+            true => match Self::decode_chunked(body) {
+                Some(decoded) => Some(decoded),
+                None => {
+                    let error = Response::from_response(StatusCode::INTERNAL_SERVER_ERROR, "");
+                    return error.frame(version, false, Framing::Full, head_request);
+                }
+            },
+            false => None,
+        };
+        let body = decoded.as_deref().unwrap_or(body);
+
+        let keep_alive = keep_alive && !close && !(stream && length.is_none() && http_10);
+        match length {
+            Some(length) => {
+                framed.extend_from_slice(b"Content-Length: ");
+                framed.extend_from_slice(itoa::Buffer::new().format(length).as_bytes());
+                framed.extend_from_slice(b"\r\n");
+            }
+            None if stream && !http_10 => {
+                framed.extend_from_slice(b"Transfer-Encoding: chunked\r\n")
+            }
+            None => {}
+        }
+        if !has_length && !no_body && (http_10 || !chunked) {
+            framed.extend_from_slice(b"Content-Length: ");
+            framed.extend_from_slice(itoa::Buffer::new().format(body.len()).as_bytes());
+            framed.extend_from_slice(b"\r\n");
+        }
+        match (keep_alive, http_10) {
+            _ if framing == Framing::Upgrade => {}
+            (true, true) if !has_connection => {
+                framed.extend_from_slice(b"Connection: keep-alive\r\n")
+            }
+            (false, false) if !close => framed.extend_from_slice(b"Connection: close\r\n"),
+            _ => {}
+        }
+        framed.extend_from_slice(b"\r\n");
+        let body = if head_request { &[][..] } else { body };
+        framed.extend_from_slice(body);
+
+        Ok(Framed {
+            bytes: framed.freeze(),
+            body: body.len(),
+            keep_alive,
+            framing,
+        })
+    }
+
+    #[inline]
+    /// How the body is delimited, without looking at [DEF_PAGES].
+    pub(crate) fn framing(&self) -> Framing {
+        match (&self.upgrade, &self.stream) {
+            (Some(_), _) => Framing::Upgrade,
+            (None, Some(stream)) => Framing::Stream(stream.map(BodyStream::length).flatten()),
+            (None, None) => Framing::Full,
+        }
+    }
+
+    #[inline]
+    /// Decodes a complete chunked body, trailers are dropped.
+    /// Returns [None] if the body is not valid chunked coding.
+    fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+        let mut decoded = Vec::with_capacity(body.len());
+
+        loop {
+            let end = body.windows(2).position(|window| window == b"\r\n")?;
+            let size = std::str::from_utf8(&body[..end]).ok()?;
+            let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+            body = &body[end + 2..];
+
+            if size == 0 {
+                return Some(decoded);
+            }
+            decoded.extend_from_slice(body.get(..size)?);
+            body = body.get(size..)?.strip_prefix(b"\r\n")?;
+        }
+    }
 }
 
 /// Functions for creating [Response].
@@ -91,6 +375,8 @@ impl Response {
             body: BytesMut::new(),
             cookies: BytesMut::new(),
             headers: BytesMut::new(),
+            upgrade: None,
+            stream: None,
         }
     }
 
@@ -189,6 +475,42 @@ impl Response {
         response.set_file(file_path, type_file).await?;
         Ok(response)
     }
+
+    #[inline]
+    /// Creating a new instance of a [Response] streaming Server-Sent Events (`text/event-stream`)
+    /// and the [EventSender] for it. The response ends when every [EventSender] is dropped.
+    ///
+    /// # Parameters
+    /// * `keep_alive` - Interval of the comments sent while no event is sent,
+    ///   they keep proxies from closing the idle connection.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Event, Request, Response};
+    /// use std::time::Duration;
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     let (response, sender) = Response::from_events(Some(Duration::from_secs(15)));
+    ///
+    ///     tokio::spawn(async move {
+    ///         let event = Event::from_fn(|event| event.data = Some(String::from("ready")));
+    ///         let _ = sender.send(event).await;
+    ///     });
+    ///     response
+    /// }
+    /// ```
+    pub fn from_events(keep_alive: Option<Duration>) -> (Self, EventSender) {
+        let (sender, receiver) = mpsc::channel(32);
+        let keep_alive =
+            keep_alive.map(|interval| (interval, Bytes::from_static(b": keep-alive\n\n")));
+
+        let mut response = Response::from_response(StatusCode::OK, "");
+        response.add_header("Content-Type", "text/event-stream");
+        response.add_header("Cache-Control", "no-cache");
+        response.stream = Some(Shared::new(BodyStream::channel(receiver, keep_alive)));
+
+        (response, EventSender::new(sender))
+    }
 }
 
 /// Functions to change [Response].
@@ -301,20 +623,29 @@ impl Response {
     }
 
     #[inline]
-    /// Writing a file to [Response].
+    /// Writing a file to [Response]. The file is kept open and sent in chunks with its
+    /// `Content-Length` when the response is written, it is not read into memory.
+    /// The `ETag` and `Last-Modified` headers are set from the file metadata, so requests
+    /// with `If-None-Match` or `If-Modified-Since` for an unchanged file are answered with 304.
+    ///
+    /// The response advertises `Accept-Ranges: bytes`. A `GET` request with a `Range` header
+    /// is answered with `206 Partial Content` and the requested bytes, several ranges as
+    /// `multipart/byteranges`, or with `416 Range Not Satisfiable` if none is in the file.
+    /// With `If-Range` the ranges are served only if it matches the `ETag` or `Last-Modified`
+    /// header of the response, otherwise the whole file is sent.
     ///
     /// # Parameters
     /// * `file_path` - Path to the file.
     /// * `type_file` - Content type of the file.
     ///
     /// # Examples
-    /// ```no_run
-    /// use maker_web::Response;
+    /// ```
+    /// use maker_web::{Request, Response, ServerError};
     ///
-    /// #[tokio::main]
-    /// async fn main() {
+    /// async fn work(_request: Request) -> Result<Response, ServerError> {
     ///     let mut response = Response::new();
-    ///     response.set_file("path", "html/text").await.unwrap();
+    ///     response.set_file("./video.mp4", "video/mp4").await?;
+    ///     Ok(response)
     /// }
     /// ```
     pub async fn set_file<Q: AsRef<Path>, W: AsRef<[u8]>>(
@@ -322,27 +653,92 @@ impl Response {
         file_path: Q,
         type_file: W,
     ) -> Result<(), ServerError> {
-        let metadata = fs::metadata(&file_path)
+        let file = File::open(file_path)
             .await
             .map_err(ServerError::OpeningFile)?;
+        let metadata = file.metadata().await.map_err(ServerError::OpeningFile)?;
         if !metadata.is_file() {
             return Err(ServerError::FolderInsteadFile);
         }
 
-        let mut file = File::open(file_path)
-            .await
-            .map_err(ServerError::OpeningFile)?;
-
-        let mut buffer = vec![0; metadata.len() as usize];
-        file.read_exact(&mut buffer)
-            .await
-            .map_err(ServerError::Read)?;
-
         self.status_code = StatusCode::OK;
-        self.body = BytesMut::from(buffer.as_slice());
+        self.body = BytesMut::from(&b"\r\n"[..]);
         self.add_header("Content-Type", type_file);
+        if let Ok(modified) = metadata.modified() {
+            let nanos = modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |dur| dur.as_nanos());
+            self.set_etag(format!("{nanos:x}-{:x}", metadata.len()));
+            self.set_last_modified(modified);
+        }
+        self.stream = Some(Shared::new(BodyStream::file(file, metadata.len())));
         Ok(())
     }
+
+    #[inline]
+    /// Hands the connection to `on_upgrade` after the response is written, for protocols
+    /// started with an HTTP handshake. The callback runs for a 101 Switching Protocols response
+    /// to an HTTP/1.1 request and for a 2xx response to a `CONNECT` request, the response
+    /// is then sent without `Content-Length` and the server no longer reads requests from
+    /// the connection. Any other response is sent normally and the callback is dropped,
+    /// as it is on HTTP/2 connections.
+    ///
+    /// # Parameters
+    /// * `on_upgrade` - Asynchronous function receiving the connection as [Upgraded].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{CleanServer, Request, Response};
+    /// use http::StatusCode;
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     let mut response = Response::from_response(StatusCode::OK, "");
+    ///     response.set_upgrade(|mut io| async move {
+    ///         let data = CleanServer::read_bytes(&mut io).await.unwrap();
+    ///         CleanServer::write(&mut io, data).await.unwrap();
+    ///     });
+    ///     response
+    /// }
+    /// ```
+    pub fn set_upgrade<F, Fut>(&mut self, on_upgrade: F)
+    where
+        F: FnOnce(Upgraded) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.upgrade = Some(OnUpgrade::new(on_upgrade));
+    }
+
+    #[inline]
+    /// Sends the body in chunks received from `receiver` instead of [Response::body],
+    /// until all senders are dropped. HTTP/1.1 uses chunked transfer coding,
+    /// HTTP/1.0 closes the connection after the body.
+    ///
+    /// # Parameters
+    /// * `receiver` - Receiver of the body chunks.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Response;
+    /// use bytes::Bytes;
+    /// use tokio::sync::mpsc;
+    ///
+    /// async fn work() -> Response {
+    ///     let (sender, receiver) = mpsc::channel(8);
+    ///     tokio::spawn(async move {
+    ///         for line in ["first\n", "second\n"] {
+    ///             let _ = sender.send(Bytes::from(line)).await;
+    ///         }
+    ///     });
+    ///
+    ///     let mut response = Response::from_body("");
+    ///     response.set_stream(receiver);
+    ///     response
+    /// }
+    /// ```
+    pub fn set_stream(&mut self, receiver: mpsc::Receiver<Bytes>) {
+        self.body = BytesMut::from(&b"\r\n"[..]);
+        self.stream = Some(Shared::new(BodyStream::channel(receiver, None)));
+    }
 }
 
 /// Functions to change Cookies and HTTP Headers.
@@ -419,3 +815,103 @@ impl Response {
         self.headers.extend_from_slice(b"\r\n");
     }
 }
+
+/// Functions to set the validators of [Response].
+impl Response {
+    #[inline]
+    /// Sets the strong `ETag` header, replacing an existing one. The server answers `GET`
+    /// and `HEAD` requests with a matching `If-None-Match` with 304 and ones with a differing
+    /// `If-Match` with 412.
+    ///
+    /// # Parameters
+    /// * `etag` - Opaque tag without quotes, it must not contain `"`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Response;
+    ///
+    /// let mut response = Response::from_body("data");
+    /// response.set_etag("v1");
+    ///
+    /// assert_eq!(response.headers.as_ref(), b"ETag: \"v1\"\r\n");
+    /// ```
+    pub fn set_etag<Q: AsRef<str>>(&mut self, etag: Q) {
+        self.remove_header("ETag");
+        self.add_header("ETag", format!("\"{}\"", etag.as_ref()));
+    }
+
+    #[inline]
+    /// Sets the weak `ETag` header, replacing an existing one. A weak tag marks
+    /// semantically equivalent responses, it satisfies `If-None-Match` but never
+    /// `If-Match` or `If-Range`.
+    ///
+    /// # Parameters
+    /// * `etag` - Opaque tag without quotes, it must not contain `"`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Response;
+    ///
+    /// let mut response = Response::from_body("data");
+    /// response.set_weak_etag("v1");
+    ///
+    /// assert_eq!(response.headers.as_ref(), b"ETag: W/\"v1\"\r\n");
+    /// ```
+    pub fn set_weak_etag<Q: AsRef<str>>(&mut self, etag: Q) {
+        self.remove_header("ETag");
+        self.add_header("ETag", format!("W/\"{}\"", etag.as_ref()));
+    }
+
+    #[inline]
+    /// Sets the `Last-Modified` header, replacing an existing one. The server answers `GET`
+    /// and `HEAD` requests with a later or equal `If-Modified-Since` with 304 and ones
+    /// with an earlier `If-Unmodified-Since` with 412.
+    ///
+    /// # Parameters
+    /// * `time` - Modification time, sent with a precision of seconds.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Response;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let mut response = Response::from_body("data");
+    /// response.set_last_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777));
+    ///
+    /// assert_eq!(
+    ///     response.headers.as_ref(),
+    ///     b"Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n"
+    /// );
+    /// ```
+    pub fn set_last_modified(&mut self, time: SystemTime) {
+        self.remove_header("Last-Modified");
+        self.add_header("Last-Modified", http_date(time));
+    }
+}
+
+/// Functions for finding and removing the headers of [Response].
+impl Response {
+    #[inline]
+    /// Value of the first header with the name, compared case-insensitively.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers[..]
+            .split(|byte| *byte == b'\n')
+            .find_map(|line| {
+                let (key, value) = std::str::from_utf8(line).ok()?.split_once(':')?;
+                key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+    }
+
+    #[inline]
+    /// Removes all headers with the name, compared case-insensitively.
+    pub(crate) fn remove_header(&mut self, name: &str) {
+        let mut headers = BytesMut::with_capacity(self.headers.len());
+        for line in self.headers.split_inclusive(|byte| *byte == b'\n') {
+            let key = line.split(|byte| *byte == b':').next().unwrap_or_default();
+            if !key.trim_ascii().eq_ignore_ascii_case(name.as_bytes()) {
+                headers.extend_from_slice(line);
+            }
+        }
+        self.headers = headers;
+    }
+}
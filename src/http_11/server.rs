@@ -1,7 +1,17 @@
+use crate::http_11::conditional::ConditionalRequest;
+use crate::http_11::metrics::ConnectionGuard;
+use crate::http_11::range::RangeRequest;
+use crate::http_11::response::{Framed, Framing};
+use crate::http_11::stream::BodyStream;
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+use crate::http_11::stream::Segment;
+use crate::shared::Shared;
+use crate::upgrade::OnUpgrade;
 use crate::*;
 
 /// HTTP communication map default code and file status.
-pub static DEF_PAGES: Lazy<DashMap<StatusCode, Bytes>> = Lazy::new(DashMap::new);
+/// Also used for the responses sent when a request cannot be parsed.
+pub static DEF_PAGES: Lazy<DashMap<StatusCode, Response>> = Lazy::new(DashMap::new);
 
 #[macro_export]
 /// Macro for setting DEF_PAGES.
@@ -26,31 +36,430 @@ pub static DEF_PAGES: Lazy<DashMap<StatusCode, Bytes>> = Lazy::new(DashMap::new)
 macro_rules! set_def_pages {
     ($(($code:expr, $page:expr)),* $(,)? ) => {{
         $(
-            match $page.as_bytes() {
-                Ok(bytes) => drop(DEF_PAGES.insert($code, bytes)),
-                Err(err) => eprintln!("DEF_PAGES | ERROR | {err}"),
+            let page = $page;
+            match page.as_bytes() {
+                Ok(_) => drop(DEF_PAGES.insert($code, page)),
+                Err(err) => $crate::http_11::server::def_pages_error(&err),
             }
         )*
     }};
     ($code:expr, $page:expr) => {{
-        match $page.as_bytes() {
-            Ok(bytes) => drop(DEF_PAGES.insert($code, bytes)),
-            Err(err) => eprintln!("DEF_PAGES | ERROR | {err}"),
+        let page = $page;
+        match page.as_bytes() {
+            Ok(_) => drop(DEF_PAGES.insert($code, page)),
+            Err(err) => $crate::http_11::server::def_pages_error(&err),
         }
     }};
 }
 
+#[doc(hidden)]
+/// Reports a page rejected by [set_def_pages].
+pub fn def_pages_error(err: &ServerError) {
+    log_event!(error, "DEF_PAGES | ERROR | {err}");
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Time limits of a connection, [None] disables the limit.
+pub struct Timeouts {
+    /// Reading the request line and headers, exceeding it answers 408.
+    pub head: Option<Duration>,
+    /// Reading the request body, exceeding it answers 408.
+    pub body: Option<Duration>,
+    /// Running the handler, exceeding it answers 503.
+    pub handler: Option<Duration>,
+    /// Writing the response.
+    pub write: Option<Duration>,
+    /// Waiting for the next request on a kept-alive connection.
+    /// [None] closes the connection after every response.
+    pub keep_alive: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    /// 30 seconds for the head and the write, 60 for the body, 5 of keep-alive,
+    /// no handler limit.
+    fn default() -> Self {
+        Timeouts {
+            head: Some(Duration::from_secs(30)),
+            body: Some(Duration::from_secs(60)),
+            handler: None,
+            write: Some(Duration::from_secs(30)),
+            keep_alive: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+#[inline]
+/// Runs the future within the limit, returns `err` if it takes longer.
+pub(crate) async fn with_timeout<F: Future>(
+    limit: Option<Duration>,
+    future: F,
+    err: ServerError,
+) -> Result<F::Output, ServerError> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future).await.map_err(|_| err),
+        None => Ok(future.await),
+    }
+}
+
+/// Client of a connection.
+#[derive(Debug, Clone)]
+pub(crate) struct Peer {
+    pub(crate) addr: SocketAddr,
+    /// Plain TCP socket file bodies can be sent to with `sendfile`.
+    #[cfg(all(feature = "sendfile", target_os = "linux"))]
+    pub(crate) socket: Option<RawFd>,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<TlsInfo>,
+}
+
+impl Peer {
+    #[inline]
+    #[cfg_attr(not(feature = "tls"), allow(unused_mut))]
+    /// Adds the connection properties to the request.
+    pub(crate) fn attach(&self, mut request: Request) -> Request {
+        #[cfg(feature = "tls")]
+        {
+            request.tls = self.tls.clone();
+        }
+        request
+    }
+}
+
+/// What happens to a connection after a response.
+enum Next {
+    /// The connection waits for the next request.
+    KeepAlive,
+    /// The connection is closed.
+    Close,
+    /// The connection is passed to the callback of the response.
+    Upgrade(OnUpgrade),
+}
+
+/// What is kept from a request for the metrics, the span and the access log.
+pub(crate) struct Served {
+    addr: SocketAddr,
+    start: Instant,
+    method: Method,
+    request_size: usize,
+    entry: Option<AccessEntry>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
 /// Server with HTTP protocol
-pub struct HttpServer;
+#[derive(Clone)]
+pub struct HttpServer {
+    on_error: ErrorHook,
+    access_log: Option<Arc<AccessLog>>,
+    metrics: Option<Arc<Metrics>>,
+    pub(crate) limits: Limits,
+    pub(crate) timeouts: Timeouts,
+    connection_limit: ConnectionLimit,
+    request_slots: Option<Arc<Semaphore>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    ip_filter: Option<Arc<IpFilter>>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    #[cfg(feature = "http2")]
+    http2: bool,
+}
+
+impl Default for HttpServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Functions for creating and configuring [HttpServer].
+impl HttpServer {
+    #[inline]
+    /// Creating a default instance of a [HttpServer].
+    /// Errors are reported with [ServerError::log].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, Request, Response};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     HttpServer::new()
+    ///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     Response::from_body("All Good :)")
+    /// }
+    /// ```
+    pub fn new() -> Self {
+        HttpServer {
+            on_error: Arc::new(ServerError::log),
+            access_log: None,
+            metrics: None,
+            limits: Limits::default(),
+            timeouts: Timeouts::default(),
+            connection_limit: ConnectionLimit::default(),
+            request_slots: None,
+            rate_limiter: None,
+            ip_filter: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "http2")]
+            http2: false,
+        }
+    }
+
+    #[inline]
+    /// Creating a new instance of a [HttpServer] from a function.
+    ///
+    /// # Parameters
+    /// * `fn_edit` - Function to change [HttpServer].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::HttpServer;
+    ///
+    /// let server = HttpServer::from_fn(|server| {
+    ///     server.set_on_error(|err, addr, phase| println!("{addr:?} | {phase} | {err}"));
+    /// });
+    /// ```
+    pub fn from_fn<F: FnOnce(&mut HttpServer)>(fn_edit: F) -> Self {
+        let mut server = HttpServer::new();
+        fn_edit(&mut server);
+        server
+    }
+
+    #[inline]
+    /// Sets the callback receiving connection-level errors: failed accepts, rejected clients,
+    /// broken requests and failed writes.
+    ///
+    /// # Parameters
+    /// * `on_error` - Function receiving the error, the client address and the [ErrorPhase].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{ErrorPhase, HttpServer};
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_on_error(|err, addr, phase| {
+    ///     if phase != ErrorPhase::Check {
+    ///         err.log(addr, phase);
+    ///     }
+    /// });
+    /// ```
+    pub fn set_on_error<F>(&mut self, on_error: F)
+    where
+        F: Fn(&ServerError, Option<SocketAddr>, ErrorPhase) + Send + Sync + 'static,
+    {
+        self.on_error = Arc::new(on_error);
+    }
+
+    #[inline]
+    /// Sets the access log, an entry is written for every served request.
+    ///
+    /// # Parameters
+    /// * `access_log` - Destination and format of the access log.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{AccessLog, HttpServer, LogFormat};
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_access_log(AccessLog::new(tokio::io::stderr(), LogFormat::Common));
+    /// ```
+    pub fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(Arc::new(access_log));
+    }
+
+    #[inline]
+    /// Sets the metrics, updated for every connection, request and error.
+    ///
+    /// # Parameters
+    /// * `metrics` - Shared counters, also used to render the metrics route.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, Metrics};
+    /// use std::sync::Arc;
+    ///
+    /// let metrics = Arc::new(Metrics::new());
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_metrics(metrics.clone());
+    /// ```
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    #[inline]
+    /// Sets the size limits enforced while reading requests.
+    ///
+    /// # Parameters
+    /// * `limits` - Request line, header, body and HTTP/2 stream limits.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, Limits};
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_limits(Limits {
+    ///     body: 16 * 1024 * 1024,
+    ///     ..Limits::default()
+    /// });
+    /// ```
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    #[inline]
+    /// Sets the time limits of connections.
+    ///
+    /// # Parameters
+    /// * `timeouts` - Read, handler, write and keep-alive limits.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, Timeouts};
+    /// use std::time::Duration;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_timeouts(Timeouts {
+    ///     head: Some(Duration::from_secs(10)),
+    ///     handler: Some(Duration::from_secs(5)),
+    ///     ..Timeouts::default()
+    /// });
+    /// ```
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    #[inline]
+    /// Sets the limits of concurrent connections and requests.
+    ///
+    /// # Parameters
+    /// * `connection_limit` - Limits and the behaviour when they are reached.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{ConnectionLimit, HttpServer};
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_connection_limit(ConnectionLimit {
+    ///     max_connections: Some(1024),
+    ///     ..ConnectionLimit::default()
+    /// });
+    /// ```
+    pub fn set_connection_limit(&mut self, connection_limit: ConnectionLimit) {
+        self.connection_limit = connection_limit;
+    }
+
+    #[inline]
+    /// Sets the per-IP rate limiter, requests over the limit are answered with 429
+    /// without running the handler.
+    ///
+    /// # Parameters
+    /// * `rate_limiter` - Shared limiter, can also be used in `check_fn`.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, RateLimiter};
+    /// use std::sync::Arc;
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_rate_limiter(Arc::new(RateLimiter::new(100, 10.0)));
+    /// ```
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) {
+        self.rate_limiter = Some(rate_limiter);
+    }
+
+    #[inline]
+    /// Sets the IP allow and deny lists, rejected clients are disconnected before
+    /// `check_fn` runs.
+    ///
+    /// # Parameters
+    /// * `ip_filter` - Shared filter, its rules can be reloaded while the server runs.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, IpFilter};
+    /// use std::sync::Arc;
+    ///
+    /// let filter = Arc::new(IpFilter::from_rules("allow 127.0.0.0/8").unwrap());
+    ///
+    /// let mut server = HttpServer::new();
+    /// server.set_ip_filter(filter.clone());
+    ///
+    /// filter.set_rules("allow 127.0.0.1\nallow 10.0.0.0/8").unwrap();
+    /// ```
+    pub fn set_ip_filter(&mut self, ip_filter: Arc<IpFilter>) {
+        self.ip_filter = Some(ip_filter);
+    }
+
+    #[cfg(feature = "tls")]
+    #[inline]
+    /// Serves HTTPS: every connection performs the TLS handshake before its first request.
+    /// Connections over the [ConnectionLimit] are closed without a response.
+    ///
+    /// # Parameters
+    /// * `tls` - Certificate chain, private key and handshake timeout.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, TlsConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").await.unwrap();
+    ///
+    ///     let mut server = HttpServer::new();
+    ///     server.set_tls(tls);
+    /// }
+    /// ```
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    #[cfg(feature = "http2")]
+    #[inline]
+    /// Serves HTTP/2 alongside HTTP/1.1. Under TLS the protocol is negotiated with ALPN,
+    /// on cleartext connections clients have to start with the HTTP/2 preface (prior knowledge).
+    /// Every stream of a connection is passed to `work_fn` as a separate [Request].
+    ///
+    /// # Parameters
+    /// * `http2` - Whether HTTP/2 is accepted.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, Request};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     HttpServer::from_fn(|server| server.set_http2(true))
+    ///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(request: Request) -> String {
+    ///     format!("{:?} {}", request.version, request.url)
+    /// }
+    /// ```
+    pub fn set_http2(&mut self, http2: bool) {
+        self.http2 = http2;
+    }
+}
 
 /// Functions for starting and running the server.
 impl HttpServer {
+    #[cfg(feature = "check_stream")]
     #[inline]
-    /// Starting the server.
+    /// Starting the server with the default configuration.
     ///
     /// # Parameters
     /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
-    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request.
+    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request,
+    ///   its output can be anything implementing [IntoResponse].
     /// * `check_fn` - Asynchronous function to check TcpStream, returns true if valid.
     ///
     /// # Examples
@@ -73,23 +482,27 @@ impl HttpServer {
     ///     Response::from_body("All Good :)")
     /// }
     /// ```
-    pub async fn launch_with_check<FutC, FutW>(
+    pub async fn launch_with_check<FutC, FutW, R>(
         listener: TcpListener,
         work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
         check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
     ) where
         FutC: Future<Output = bool> + Send + Sync + 'static,
-        FutW: Future<Output = Response> + Send + Sync + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
     {
-        Self::impl_launch(listener, check_fn, work_fn).await;
+        HttpServer::new()
+            .run_with_check(listener, work_fn, check_fn)
+            .await;
     }
 
     #[inline]
-    /// Starting the server.
+    /// Starting the server with the default configuration.
     ///
     /// # Parameters
     /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
-    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request.
+    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request,
+    ///   its output can be anything implementing [IntoResponse].
     ///
     /// # Examples
     /// ```no_run
@@ -106,73 +519,738 @@ impl HttpServer {
     ///     Response::from_body("All Good :)")
     /// }
     /// ```
-    pub async fn launch<FutW>(
+    pub async fn launch<FutW, R>(
         listener: TcpListener,
         work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
     ) where
-        FutW: Future<Output = Response> + Send + Sync + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        HttpServer::new().run(listener, work_fn).await;
+    }
+
+    #[cfg(feature = "check_stream")]
+    #[inline]
+    /// Starting the configured server.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request,
+    ///   its output can be anything implementing [IntoResponse].
+    /// * `check_fn` - Asynchronous function to check TcpStream, returns true if valid.
+    ///   Clients failing the check are disconnected without a response.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, Request, Response};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     HttpServer::from_fn(|server| server.set_on_error(|_, _, _| {}))
+    ///         .run_with_check(TcpListener::bind("127.0.0.1:80").await.unwrap(), work, check)
+    ///         .await;
+    /// }
+    ///
+    /// async fn check(_: std::net::SocketAddr) -> bool { true }
+    /// async fn work(_request: Request) -> Response {
+    ///     Response::from_body("All Good :)")
+    /// }
+    /// ```
+    pub async fn run_with_check<FutC, FutW, R>(
+        self,
+        listener: TcpListener,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+        check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
+    ) where
+        FutC: Future<Output = bool> + Send + Sync + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        self.impl_launch(listener, check_fn, work_fn).await;
+    }
+
+    #[inline]
+    /// Starting the configured server.
+    ///
+    /// # Parameters
+    /// * `listener` - An asynchronous TCP listener designed for listening to incoming connections.
+    /// * `work_fn` - Asynchronous function for creating an HTTP response based on a request,
+    ///   its output can be anything implementing [IntoResponse].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, Request, Response};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     HttpServer::from_fn(|server| server.set_on_error(|_, _, _| {}))
+    ///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     Response::from_body("All Good :)")
+    /// }
+    /// ```
+    pub async fn run<FutW, R>(
+        self,
+        listener: TcpListener,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) where
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
     {
         async fn check(_: SocketAddr) -> bool {
             true
         }
 
-        Self::impl_launch(listener, check, work_fn).await;
+        self.impl_launch(listener, check, work_fn).await;
     }
 
     #[inline]
-    async fn impl_launch<FutC, FutW>(
+    async fn impl_launch<FutC, FutW, R>(
+        self,
         listener: TcpListener,
         check_fn: impl Fn(SocketAddr) -> FutC + Send + Sync + Copy + 'static,
         work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
     ) where
         FutC: Future<Output = bool> + Send + Sync + 'static,
-        FutW: Future<Output = Response> + Send + Sync + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
     {
-        #[cfg(not(feature = "check_stream"))]
-        let _ = check_fn;
+        if let Ok(addr) = listener.local_addr() {
+            log_event!(info, "SERVER | HTTP | {addr} | LAUNCH");
+        }
 
-        println!(
-            "SERVER | HTTP | {} | LAUCNH",
-            listener.local_addr().unwrap()
-        );
+        let mut server = self;
+        #[cfg(all(feature = "tls", feature = "http2"))]
+        if let (Some(tls), true) = (&mut server.tls, server.http2) {
+            tls.set_alpn(&[b"h2", b"http/1.1"]);
+        }
+        server.request_slots = ConnectionLimit::semaphore(server.connection_limit.max_requests);
+        let connection_slots = ConnectionLimit::semaphore(server.connection_limit.max_connections);
+        let server = Arc::new(server);
+        let limit = server.connection_limit;
 
         loop {
+            let waited = match limit.saturation {
+                Saturation::Wait => limit.acquire(&connection_slots).await.unwrap_or(None),
+                Saturation::Reject { .. } => None,
+            };
+
             let (socket, net_addr) = match listener.accept().await {
                 Ok((socket, net_addr)) => (socket, net_addr),
-                Err(_) => continue,
+                Err(err) => {
+                    server.report(&ServerError::Accept(err), None, ErrorPhase::Accept);
+                    continue;
+                }
             };
 
-            tokio::spawn(async move {
-                if !check_fn(net_addr).await {
-                    return Err(ServerError::VerificationFailed);
+            let permit = match waited {
+                Some(permit) => Some(permit),
+                None => match limit.acquire(&connection_slots).await {
+                    Ok(permit) => permit,
+                    Err(()) => {
+                        server.reject(socket, net_addr);
+                        continue;
+                    }
+                },
+            };
+
+            if let Some(metrics) = &server.metrics {
+                metrics.connection_opened();
+            }
+
+            let server = server.clone();
+            let connection = async move {
+                let _guard = ConnectionGuard(server.metrics.clone());
+                let _permit = permit;
+
+                let allowed = server.ip_filter.as_ref().is_none_or(|f| f.check(net_addr));
+
+                if !allowed || !check_fn(net_addr).await {
+                    let err = ServerError::VerificationFailed;
+                    return server.report(&err, Some(net_addr), ErrorPhase::Check);
                 }
 
-                Self::handle_connection(socket, net_addr, work_fn).await
-            });
+                let result = server.serve_connection(socket, net_addr, work_fn).await;
+                if let Err((err, phase)) = result {
+                    server.report(&err, Some(net_addr), phase);
+                }
+            };
+
+            #[cfg(feature = "tracing")]
+            let connection = tracing::Instrument::instrument(
+                connection,
+                tracing::info_span!("connection", peer = %net_addr),
+            );
+
+            tokio::spawn(connection);
+        }
+    }
+
+    #[inline]
+    /// Answers a connection over the limit with 503 and closes it. The response is written
+    /// without waiting, only if it fits into the socket buffer, so rejecting costs no task.
+    fn reject(&self, socket: TcpStream, addr: SocketAddr) {
+        let err = ServerError::ConnectionLimit;
+        let response = self.connection_limit.response(&err);
+
+        #[cfg(feature = "tls")]
+        let plain = self.tls.is_none();
+        #[cfg(not(feature = "tls"))]
+        let plain = true;
+
+        if plain {
+            let framed = Self::frame_response(&response, Version::HTTP_11, false, false);
+            if let (Ok(framed), Ok(mut socket)) = (framed, socket.into_std()) {
+                let _ = std::io::Write::write(&mut socket, &framed.bytes);
+            }
+        }
+        self.report(&err, Some(addr), ErrorPhase::Accept);
+    }
+
+    #[inline]
+    pub(crate) fn report(&self, err: &ServerError, addr: Option<SocketAddr>, phase: ErrorPhase) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_error(err);
         }
+
+        (self.on_error)(err, addr, phase);
     }
 
     #[inline]
-    async fn handle_connection<FutW>(
-        stream: TcpStream,
+    /// Performs the TLS handshake, if configured, and serves the connection.
+    async fn serve_connection<FutW, R>(
+        self: &Arc<Self>,
+        socket: TcpStream,
         addr: SocketAddr,
         work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
-    ) -> Result<(), ServerError>
-    where FutW: Future<Output = Response> + Send + Sync + 'static {
-        let (mut read, mut write) = io::split(stream);
+    ) -> Result<(), (ServerError, ErrorPhase)>
+    where
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let (stream, info) = tls
+                .accept(socket)
+                .await
+                .map_err(|err| (err, ErrorPhase::Handshake))?;
+
+            let peer = Peer {
+                addr,
+                #[cfg(all(feature = "sendfile", target_os = "linux"))]
+                socket: None,
+                tls: Some(info),
+            };
+            return self.serve_stream(stream, peer, work_fn).await;
+        }
 
-        let request = Request::result_from(&mut read, addr).await?;
-        let response = work_fn(request).await;
+        let peer = Peer {
+            addr,
+            #[cfg(all(feature = "sendfile", target_os = "linux"))]
+            socket: Some(socket.as_raw_fd()),
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+        self.serve_stream(socket, peer, work_fn).await
+    }
 
-        if let Some(page) = DEF_PAGES.get(&response.status_code) {
-            write.write_all(&page).await.map_err(ServerError::Write)?;
-        } else {
+    #[inline]
+    /// Serves the connection with the protocol chosen by the client.
+    async fn serve_stream<S, FutW, R>(
+        self: &Arc<Self>,
+        stream: S,
+        peer: Peer,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<(), (ServerError, ErrorPhase)>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        #[cfg(feature = "http2")]
+        if self.http2 {
+            #[cfg(feature = "tls")]
+            if let Some(tls) = &peer.tls {
+                return match tls.alpn_protocol.as_deref() {
+                    Some("h2") => self.serve_h2(stream, peer, work_fn).await,
+                    _ => self.handle_connection(stream, peer, work_fn).await,
+                };
+            }
+
+            let sniff = http_2::sniff(stream);
+            let (h2, stream) = with_timeout(self.timeouts.head, sniff, ServerError::HeadTimeout)
+                .await
+                .and_then(|sniffed| sniffed.map_err(ServerError::Read))
+                .map_err(|err| (err, ErrorPhase::Read))?;
+
+            return match h2 {
+                true => self.serve_h2(stream, peer, work_fn).await,
+                false => self.handle_connection(stream, peer, work_fn).await,
+            };
+        }
+
+        self.handle_connection(stream, peer, work_fn).await
+    }
+
+    #[inline]
+    async fn handle_connection<S, FutW, R>(
+        &self,
+        stream: S,
+        peer: Peer,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<(), (ServerError, ErrorPhase)>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        let (read, mut write) = io::split(stream);
+        let mut reader = BufReader::new(read);
+
+        loop {
+            let (request, size) =
+                match Request::result_from(&mut reader, peer.addr, &self.limits, &self.timeouts)
+                    .await
+                {
+                    Ok((request, size)) => (peer.attach(request), size),
+                    Err((err, version)) => {
+                        let version = version.unwrap_or(Version::HTTP_11);
+                        if let Some((bytes, body)) =
+                            self.write_error(&mut write, &err, version).await
+                        {
+                            self.finish_rejected(peer.addr, &err, bytes, body).await;
+                        }
+                        return Err((err, ErrorPhase::Read));
+                    }
+                };
+
+            match self
+                .serve_request(&mut write, request, size, &peer, work_fn)
+                .await?
+            {
+                Next::KeepAlive => {}
+                Next::Close => return Ok(()),
+                Next::Upgrade(upgrade) => {
+                    let buffered = Bytes::copy_from_slice(reader.buffer());
+                    let stream = reader.into_inner().unsplit(write);
+
+                    upgrade.run(Upgraded::new(buffered, stream)).await;
+                    return Ok(());
+                }
+            }
+
+            let Some(idle) = self.timeouts.keep_alive else {
+                return Ok(());
+            };
+            match tokio::time::timeout(idle, reader.fill_buf()).await {
+                Ok(Ok([])) => return Ok(()),
+                Ok(Ok(_)) => continue,
+                Ok(Err(err)) => return Err((ServerError::Read(err), ErrorPhase::Read)),
+                Err(_) => return Err((ServerError::IdleTimeout, ErrorPhase::Read)),
+            }
+        }
+    }
+
+    #[inline]
+    /// Runs the handler and writes its response, returns what happens to the connection next.
+    async fn serve_request<W, FutW, R>(
+        &self,
+        write: &mut W,
+        request: Request,
+        size: usize,
+        peer: &Peer,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<Next, (ServerError, ErrorPhase)>
+    where
+        W: AsyncWriteExt + Unpin,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        let version = request.version;
+        let connect = request.method == Method::CONNECT;
+        let head = request.method == Method::HEAD;
+        let keep_alive = self.timeouts.keep_alive.is_some() && request.keep_alive();
+        let served = self.begin(&request, size, peer.addr);
+
+        let mut response = match self.respond(request, &served, work_fn).await {
+            Ok(response) => response,
+            Err((err, response)) => {
+                if let Some(response) = response {
+                    let _ = self
+                        .write_response(write, &response, version, false, head)
+                        .await;
+                }
+                return Err((err, ErrorPhase::Handle));
+            }
+        };
+        let takeover = match connect {
+            true => response.status_code.is_success(),
+            false => {
+                response.status_code == StatusCode::SWITCHING_PROTOCOLS
+                    && version == Version::HTTP_11
+            }
+        };
+        if !takeover {
+            response.upgrade = None;
+        }
+
+        let framed = self
+            .write_response(write, &response, version, keep_alive, head)
+            .await
+            .map_err(|err| (err, ErrorPhase::Write))?;
+        let (mut bytes, mut body) = (framed.bytes.len(), framed.body);
+
+        if let (Framing::Stream(length), false) = (framed.framing, head) {
+            let stream = response.stream.as_ref().and_then(Shared::take);
+            let chunked = length.is_none() && version != Version::HTTP_10;
+            let (chunks, streamed) = self
+                .write_stream(write, stream, chunked, peer)
+                .await
+                .map_err(|err| (err, ErrorPhase::Write))?;
+            bytes += chunks;
+            body += streamed;
+        }
+
+        self.finish(served, response.status_code, bytes, body).await;
+
+        Ok(match (framed.framing, response.upgrade) {
+            (Framing::Upgrade, Some(upgrade)) => Next::Upgrade(upgrade),
+            _ if framed.keep_alive => Next::KeepAlive,
+            _ => Next::Close,
+        })
+    }
+
+    #[inline]
+    /// Collects what the metrics, the span and the access log need from the request.
+    ///
+    /// # Parameters
+    /// * `request` - Parsed request.
+    /// * `request_size` - Size of the request in bytes, counted by the protocol.
+    /// * `addr` - Client socket address.
+    pub(crate) fn begin(&self, request: &Request, request_size: usize, addr: SocketAddr) -> Served {
+        Served {
+            addr,
+            start: Instant::now(),
+            method: request.method.clone(),
+            request_size,
+            entry: self.access_log.as_ref().map(|_| AccessEntry {
+                socket_addr: addr,
+                method: Some(request.method.clone()),
+                url: Some(request.url.clone()),
+                version: Some(request.version),
+                status: StatusCode::OK,
+                size: 0,
+                referer: request.headers.get(REFERER).cloned(),
+                user_agent: request.headers.get(USER_AGENT).cloned(),
+                duration: Duration::ZERO,
+                time: SystemTime::now(),
+            }),
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "request",
+                method = %request.method,
+                path = %request.url.path(),
+                status = tracing::field::Empty,
+                latency_us = tracing::field::Empty,
+            ),
+        }
+    }
+
+    #[inline]
+    /// Runs the rate limiter, the request limit and the handler.
+    /// On error also returns the response to send before closing, if there is one.
+    pub(crate) async fn respond<FutW, R>(
+        &self,
+        request: Request,
+        served: &Served,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<Response, (ServerError, Option<Response>)>
+    where
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        let limited = self
+            .rate_limiter
+            .as_ref()
+            .and_then(|limiter| limiter.acquire(served.addr.ip()).err());
+
+        if let Some(retry_after) = limited {
+            let err = ServerError::RateLimited;
+            self.report(&err, Some(served.addr), ErrorPhase::Check);
+
+            return Ok(RateLimiter::response(retry_after));
+        }
+
+        let permit = match self.connection_limit.acquire(&self.request_slots).await {
+            Ok(permit) => permit,
+            Err(()) => {
+                let err = ServerError::RequestLimit;
+                let response = self.connection_limit.response(&err);
+
+                return Err((err, Some(response)));
+            }
+        };
+
+        let conditional = ConditionalRequest::from_request(&request);
+        let range = RangeRequest::from_request(&request);
+        let handler = work_fn(request);
+        #[cfg(feature = "tracing")]
+        let handler = tracing::Instrument::instrument(handler, served.span.clone());
+
+        let response = with_timeout(self.timeouts.handler, handler, ServerError::HandlerTimeout)
+            .await
+            .map(|response| {
+                let mut response = response.into_response();
+                conditional.apply(&mut response);
+                range.apply(&mut response);
+                response
+            });
+        drop(permit);
+
+        response.map_err(|err| {
+            let response = err.status_code().map(|_| err.response());
+            (err, response)
+        })
+    }
+
+    #[inline]
+    /// Records the served request in the metrics, the span and the access log.
+    /// The log entry is only queued, a failed log write is reported later with
+    /// [ErrorPhase::Log] and no client address, and keeps the connection open.
+    pub(crate) async fn finish(
+        &self,
+        served: Served,
+        status: StatusCode,
+        bytes: usize,
+        body: usize,
+    ) {
+        let duration = served.start.elapsed();
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_request(&served.method, status, served.request_size, bytes, duration);
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            served.span.record("status", status.as_u16());
+            served
+                .span
+                .record("latency_us", duration.as_micros() as u64);
+            tracing::info!(parent: &served.span, "request served");
+        }
+
+        if let (Some(log), Some(mut entry)) = (&self.access_log, served.entry) {
+            entry.status = status;
+            entry.size = body;
+            entry.duration = duration;
+
+            if let Err(err) = log.write(&entry).await {
+                self.report(&err, None, ErrorPhase::Log);
+            }
+        }
+    }
+
+    #[inline]
+    /// Records a request answered with an error before it could be parsed in the metrics
+    /// and the access log, with the request line left unknown.
+    pub(crate) async fn finish_rejected(
+        &self,
+        addr: SocketAddr,
+        err: &ServerError,
+        bytes: usize,
+        body: usize,
+    ) {
+        let Some(status) = err.status_code() else {
+            return;
+        };
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_rejected(status, bytes);
+        }
+
+        if let Some(log) = &self.access_log {
+            let entry = AccessEntry {
+                socket_addr: addr,
+                method: None,
+                url: None,
+                version: None,
+                status,
+                size: body,
+                referer: None,
+                user_agent: None,
+                duration: Duration::ZERO,
+                time: SystemTime::now(),
+            };
+
+            if let Err(err) = log.write(&entry).await {
+                self.report(&err, None, ErrorPhase::Log);
+            }
+        }
+    }
+
+    #[inline]
+    /// Writes the response matching the error, if there is one, before closing the connection.
+    /// Returns the sizes of the written response and its body.
+    async fn write_error<W: AsyncWriteExt + Unpin>(
+        &self,
+        write: &mut W,
+        err: &ServerError,
+        version: Version,
+    ) -> Option<(usize, usize)> {
+        err.status_code()?;
+
+        let framed = self
+            .write_response(write, &err.response(), version, false, false)
+            .await
+            .ok()?;
+        Some((framed.bytes.len(), framed.body))
+    }
+
+    #[inline]
+    /// Writes the response, or its page from [DEF_PAGES], with the framing headers of the version.
+    /// Only the head is written for a `HEAD` request.
+    async fn write_response<W: AsyncWriteExt + Unpin>(
+        &self,
+        write: &mut W,
+        response: &Response,
+        version: Version,
+        keep_alive: bool,
+        head: bool,
+    ) -> Result<Framed, ServerError> {
+        let framed = Self::frame_response(response, version, keep_alive, head)?;
+
+        let send = async {
             write
-                .write_all(&response.as_bytes()?)
+                .write_all(&framed.bytes)
                 .await
                 .map_err(ServerError::Write)?;
+            write.flush().await.map_err(ServerError::Flush)
+        };
+        with_timeout(self.timeouts.write, send, ServerError::WriteTimeout).await??;
+
+        Ok(framed)
+    }
+
+    #[inline]
+    /// Serializes the response, or its page from [DEF_PAGES], with the framing headers
+    /// of the version.
+    fn frame_response(
+        response: &Response,
+        version: Version,
+        keep_alive: bool,
+        head: bool,
+    ) -> Result<Framed, ServerError> {
+        match DEF_PAGES.get(&response.status_code) {
+            Some(page) => page.frame(version, keep_alive, Framing::Full, head),
+            None => response.frame(version, keep_alive, response.framing(), head),
         }
+    }
+
+    #[inline]
+    /// Writes a streamed body after its head, as chunks or as is until the connection closes.
+    /// Returns the number of bytes written and the size of the body.
+    async fn write_stream<W: AsyncWriteExt + Unpin>(
+        &self,
+        write: &mut W,
+        stream: Option<BodyStream>,
+        chunked: bool,
+        #[cfg_attr(
+            not(all(feature = "sendfile", target_os = "linux")),
+            allow(unused_variables)
+        )]
+        peer: &Peer,
+    ) -> Result<(usize, usize), ServerError> {
+        let (mut bytes, mut body) = (0, 0);
+
+        #[cfg(all(feature = "sendfile", target_os = "linux"))]
+        let stream = match (stream, peer.socket) {
+            (Some(BodyStream::File { file, segments, .. }), Some(socket)) => {
+                return self.send_segments(write, socket, file, segments).await;
+            }
+            (stream, _) => stream,
+        };
+
+        if let Some(mut stream) = stream {
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(ServerError::Read)?;
+                let data = match chunked {
+                    true => {
+                        let mut data = BytesMut::with_capacity(chunk.len() + 12);
+                        data.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                        data.extend_from_slice(&chunk);
+                        data.extend_from_slice(b"\r\n");
+                        data.freeze()
+                    }
+                    false => chunk.clone(),
+                };
 
-        write.flush().await.map_err(ServerError::Flush)
+                let send = async {
+                    write.write_all(&data).await.map_err(ServerError::Write)?;
+                    write.flush().await.map_err(ServerError::Flush)
+                };
+                with_timeout(self.timeouts.write, send, ServerError::WriteTimeout).await??;
+
+                bytes += data.len();
+                body += chunk.len();
+            }
+        }
+
+        if chunked {
+            let send = async {
+                write
+                    .write_all(b"0\r\n\r\n")
+                    .await
+                    .map_err(ServerError::Write)?;
+                write.flush().await.map_err(ServerError::Flush)
+            };
+            with_timeout(self.timeouts.write, send, ServerError::WriteTimeout).await??;
+            bytes += 5;
+        }
+
+        Ok((bytes, body))
     }
-}
\ No newline at end of file
+
+    #[cfg(all(feature = "sendfile", target_os = "linux"))]
+    #[inline]
+    /// Writes a file body with `sendfile`, the bytes between its ranges are written as is.
+    /// Returns the number of bytes written and the size of the body.
+    async fn send_segments<W: AsyncWriteExt + Unpin>(
+        &self,
+        write: &mut W,
+        socket: RawFd,
+        file: File,
+        segments: VecDeque<Segment>,
+    ) -> Result<(usize, usize), ServerError> {
+        let file = Arc::new(file.into_std().await);
+        let mut sent = 0;
+
+        for segment in segments {
+            match segment {
+                Segment::Bytes(bytes) => {
+                    let send = async {
+                        write.write_all(&bytes).await.map_err(ServerError::Write)?;
+                        write.flush().await.map_err(ServerError::Flush)
+                    };
+                    with_timeout(self.timeouts.write, send, ServerError::WriteTimeout).await??;
+                    sent += bytes.len();
+                }
+                Segment::Range { offset, length } => {
+                    let file = file.clone();
+                    sent += sendfile::send_file(socket, file, offset, length, self.timeouts.write)
+                        .await
+                        .map_err(|err| match err.kind() {
+                            io::ErrorKind::TimedOut => ServerError::WriteTimeout,
+                            _ => ServerError::Write(err),
+                        })? as usize;
+                }
+            }
+        }
+
+        Ok((sent, sent))
+    }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+/// Server-Sent Event, sent with [EventSender] on a response created by [Response::from_events].
+pub struct Event {
+    /// Event type, the client dispatches `message` without it.
+    pub event: Option<String>,
+    /// Event data, sent as one `data` field per line.
+    pub data: Option<String>,
+    /// Event ID, the client sends the last one back in `Last-Event-ID` when it reconnects.
+    pub id: Option<String>,
+    /// Reconnection delay for the client.
+    pub retry: Option<Duration>,
+}
+
+/// Functions for creating [Event].
+impl Event {
+    #[inline]
+    /// Creating a new instance of an [Event] with data.
+    ///
+    /// # Parameters
+    /// * `data` - Event data, may contain several lines.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Event;
+    ///
+    /// let event = Event::from_data("Hello");
+    /// assert_eq!(event.data.as_deref(), Some("Hello"));
+    /// ```
+    pub fn from_data<Q: Into<String>>(data: Q) -> Self {
+        Event {
+            data: Some(data.into()),
+            ..Event::default()
+        }
+    }
+
+    #[inline]
+    /// Creating a new instance of an [Event] from a function.
+    ///
+    /// # Parameters
+    /// * `fn_edit` - Function to change [Event].
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Event;
+    ///
+    /// let event = Event::from_fn(|event| {
+    ///     event.event = Some("update".to_string());
+    ///     event.data = Some("{\"count\": 1}".to_string());
+    ///     event.id = Some("1".to_string());
+    /// });
+    /// ```
+    pub fn from_fn<F: FnOnce(&mut Event)>(fn_edit: F) -> Self {
+        let mut event = Event::default();
+        fn_edit(&mut event);
+        event
+    }
+}
+
+/// Functions for formatting [Event].
+impl Event {
+    #[inline]
+    /// Translation of [Event] into the `text/event-stream` format.
+    /// Line breaks are removed from the type and the ID, data is split into `data` lines.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::Event;
+    /// use std::time::Duration;
+    ///
+    /// let event = Event::from_fn(|event| {
+    ///     event.event = Some("update".to_string());
+    ///     event.data = Some("first\nsecond".to_string());
+    ///     event.id = Some("7".to_string());
+    ///     event.retry = Some(Duration::from_secs(3));
+    /// });
+    ///
+    /// assert_eq!(
+    ///     event.as_bytes(),
+    ///     "event: update\ndata: first\ndata: second\nid: 7\nretry: 3000\n\n"
+    /// );
+    /// ```
+    pub fn as_bytes(&self) -> Bytes {
+        let mut bytes = BytesMut::new();
+        let mut field = |name: &str, value: &str| {
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(b": ");
+            bytes.extend_from_slice(value.as_bytes());
+            bytes.extend_from_slice(b"\n");
+        };
+        let single_line = |value: &str| value.replace(['\r', '\n'], "");
+
+        if let Some(event) = &self.event {
+            field("event", &single_line(event));
+        }
+        if let Some(data) = &self.data {
+            for line in data.split("\r\n").flat_map(|line| line.split(['\r', '\n'])) {
+                field("data", line);
+            }
+        }
+        if let Some(id) = &self.id {
+            field("id", &single_line(id));
+        }
+        if let Some(retry) = self.retry {
+            field("retry", itoa::Buffer::new().format(retry.as_millis()));
+        }
+
+        bytes.extend_from_slice(b"\n");
+        bytes.freeze()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Sending half of a response created by [Response::from_events].
+/// The stream ends when all clones are dropped.
+pub struct EventSender {
+    sender: mpsc::Sender<Bytes>,
+}
+
+/// Functions for sending [Event].
+impl EventSender {
+    #[inline]
+    pub(crate) fn new(sender: mpsc::Sender<Bytes>) -> Self {
+        Self { sender }
+    }
+
+    #[inline]
+    /// Sends an event, waits while the client is slower than the events are produced.
+    /// Returns [ServerError::StreamClosed] once the client is gone.
+    ///
+    /// # Parameters
+    /// * `event` - Event to send.
+    pub async fn send(&self, event: Event) -> Result<(), ServerError> {
+        self.send_bytes(event.as_bytes()).await
+    }
+
+    #[inline]
+    /// Sends a comment line, which clients ignore.
+    ///
+    /// # Parameters
+    /// * `comment` - Comment text, line breaks are removed.
+    pub async fn send_comment(&self, comment: &str) -> Result<(), ServerError> {
+        let comment = format!(": {}\n\n", comment.replace(['\r', '\n'], ""));
+        self.send_bytes(Bytes::from(comment)).await
+    }
+
+    #[inline]
+    /// Whether the client is gone and nothing more can be sent.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    #[inline]
+    async fn send_bytes(&self, bytes: Bytes) -> Result<(), ServerError> {
+        self.sender
+            .send(bytes)
+            .await
+            .map_err(|_| ServerError::StreamClosed)
+    }
+}
@@ -0,0 +1,323 @@
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Handler serving the files of a directory under a URL prefix.
+///
+/// [StaticFiles::serve] answers only `GET` and `HEAD` requests under the prefix,
+/// so it can be mounted in front of the application handler.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{HttpServer, Request, Response, ServerError, StaticFiles};
+/// use once_cell::sync::Lazy;
+/// use tokio::net::TcpListener;
+///
+/// static FILES: Lazy<StaticFiles> = Lazy::new(|| StaticFiles::new("/static", "./public"));
+///
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::launch(TcpListener::bind("127.0.0.1:80").await.unwrap(), work).await;
+/// }
+///
+/// async fn work(request: Request) -> Result<Response, ServerError> {
+///     if let Some(response) = FILES.serve(&request).await {
+///         return response;
+///     }
+///
+///     Ok(Response::from_body("Application"))
+/// }
+/// ```
+pub struct StaticFiles {
+    prefix: String,
+    resolver: PathResolver,
+    index: Option<String>,
+    listing: bool,
+}
+
+/// Functions for creating and configuring [StaticFiles].
+impl StaticFiles {
+    #[inline]
+    /// Creating a new instance of a [StaticFiles], serving `index.html` for directories
+    /// and no directory listings.
+    ///
+    /// # Parameters
+    /// * `prefix` - URL path the directory is mounted at, e.g. `/static` or `/`.
+    /// * `root` - Directory with the files.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StaticFiles;
+    ///
+    /// let files = StaticFiles::new("/static/", "./public");
+    /// ```
+    pub fn new<Q: AsRef<str>, P: AsRef<Path>>(prefix: Q, root: P) -> Self {
+        StaticFiles {
+            prefix: prefix.as_ref().trim_end_matches('/').to_string(),
+            resolver: PathResolver::new(root),
+            index: Some("index.html".to_string()),
+            listing: false,
+        }
+    }
+
+    #[inline]
+    /// Sets the file served for a directory.
+    ///
+    /// # Parameters
+    /// * `index` - File name, [None] to serve no file for directories.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StaticFiles;
+    ///
+    /// let mut files = StaticFiles::new("/", "./public");
+    /// files.set_index(Some("default.htm"));
+    /// ```
+    pub fn set_index<Q: AsRef<str>>(&mut self, index: Option<Q>) {
+        self.index = index.map(|index| index.as_ref().to_string());
+    }
+
+    #[inline]
+    /// Sets whether a directory without an index file is answered with an HTML listing
+    /// of its entries instead of 404.
+    ///
+    /// # Parameters
+    /// * `listing` - Whether to render directory listings.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StaticFiles;
+    ///
+    /// let mut files = StaticFiles::new("/downloads", "./downloads");
+    /// files.set_listing(true);
+    /// ```
+    pub fn set_listing(&mut self, listing: bool) {
+        self.listing = listing;
+    }
+
+    #[inline]
+    /// Sets whether symbolic links inside the directory may point out of it,
+    /// see [PathResolver::set_symlink_escape].
+    ///
+    /// # Parameters
+    /// * `symlink_escape` - Whether to follow such links instead of answering 403.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StaticFiles;
+    ///
+    /// let mut files = StaticFiles::new("/static", "./public");
+    /// files.set_symlink_escape(true);
+    /// ```
+    pub fn set_symlink_escape(&mut self, symlink_escape: bool) {
+        self.resolver.set_symlink_escape(symlink_escape);
+    }
+}
+
+/// Functions for serving files with [StaticFiles].
+impl StaticFiles {
+    #[inline]
+    /// Answers the request with the file under the prefix, resolved with [PathResolver].
+    /// Returns [None] for other paths and methods, missing files answer 404
+    /// and paths leaving the directory 403.
+    /// A directory requested without a trailing slash is redirected to the same path
+    /// with a trailing slash.
+    ///
+    /// # Parameters
+    /// * `request` - Request received by the handler.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Request, Response, ServerError, StaticFiles};
+    /// use once_cell::sync::Lazy;
+    ///
+    /// static FILES: Lazy<StaticFiles> = Lazy::new(|| StaticFiles::new("/static", "./public"));
+    ///
+    /// async fn work(request: Request) -> Result<Response, ServerError> {
+    ///     if let Some(response) = FILES.serve(&request).await {
+    ///         return response;
+    ///     }
+    ///
+    ///     Ok(Response::from_body("Application"))
+    /// }
+    /// ```
+    pub async fn serve(&self, request: &Request) -> Option<Result<Response, ServerError>> {
+        if request.method != Method::GET && request.method != Method::HEAD {
+            return None;
+        }
+
+        let path = request.url.path().strip_prefix(&self.prefix)?;
+        if !path.is_empty() && !path.starts_with('/') {
+            return None;
+        }
+
+        Some(self.serve_path(request, path).await)
+    }
+
+    #[inline]
+    async fn serve_path(&self, request: &Request, path: &str) -> Result<Response, ServerError> {
+        let file_path = self.resolver.resolve(path).await?;
+
+        let metadata = fs::metadata(&file_path)
+            .await
+            .map_err(ServerError::OpeningFile)?;
+        if !metadata.is_dir() {
+            return Response::from_file(&file_path, Self::content_type(&file_path)).await;
+        }
+
+        if !path.ends_with('/') {
+            let mut location = self.prefix.clone();
+            for segment in PathResolver::normalize(path)?.iter() {
+                location.push('/');
+                location.push_str(&escape_href(&segment.to_string_lossy()));
+            }
+            location.push('/');
+            if let Some(query) = request.url.query() {
+                location = format!("{location}?{query}");
+            }
+
+            let mut response = Response::new();
+            response.set_redirect_str(location);
+            return Ok(response);
+        }
+
+        if let Some(index) = &self.index {
+            let index = file_path.join(index);
+            if fs::metadata(&index).await.is_ok_and(|meta| meta.is_file()) {
+                return Response::from_file(&index, Self::content_type(&index)).await;
+            }
+        }
+        if !self.listing {
+            return Err(ServerError::FolderInsteadFile);
+        }
+
+        let parent = file_path != self.resolver.root();
+        Self::listing(request.url.path(), &file_path, parent).await
+    }
+
+    #[inline]
+    /// HTML page with links to the entries of a directory, directories first.
+    async fn listing(url: &str, dir: &Path, parent: bool) -> Result<Response, ServerError> {
+        let mut entries = Vec::new();
+        let mut read_dir = fs::read_dir(dir).await.map_err(ServerError::OpeningFile)?;
+        while let Some(entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(ServerError::OpeningFile)?
+        {
+            let is_dir = entry.file_type().await.is_ok_and(|kind| kind.is_dir());
+            entries.push((!is_dir, entry.file_name().to_string_lossy().to_string()));
+        }
+        entries.sort();
+
+        let title = escape_html(url);
+        let mut page = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n\
+            <body>\n<h1>Index of {title}</h1>\n<ul>\n"
+        );
+        if parent {
+            page.push_str("<li><a href=\"../\">../</a></li>\n");
+        }
+        for (is_file, name) in entries {
+            let slash = if is_file { "" } else { "/" };
+            page.push_str(&format!(
+                "<li><a href=\"{}{slash}\">{}{slash}</a></li>\n",
+                escape_href(&name),
+                escape_html(&name),
+            ));
+        }
+        page.push_str("</ul>\n</body>\n</html>\n");
+
+        let mut response = Response::from_response(StatusCode::OK, page);
+        response.add_header("Content-Type", "text/html; charset=utf-8");
+        Ok(response)
+    }
+
+    #[inline]
+    /// Guesses the `Content-Type` of a file from its extension,
+    /// `application/octet-stream` if it is unknown.
+    ///
+    /// # Parameters
+    /// * `path` - Path or name of the file.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::StaticFiles;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(StaticFiles::content_type(Path::new("index.HTML")), "text/html; charset=utf-8");
+    /// assert_eq!(StaticFiles::content_type(Path::new("video.mp4")), "video/mp4");
+    /// assert_eq!(StaticFiles::content_type(Path::new("README")), "application/octet-stream");
+    /// ```
+    pub fn content_type(path: &Path) -> &'static str {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "html" | "htm" => "text/html; charset=utf-8",
+            "css" => "text/css; charset=utf-8",
+            "js" | "mjs" => "text/javascript; charset=utf-8",
+            "json" | "map" => "application/json",
+            "txt" => "text/plain; charset=utf-8",
+            "md" => "text/markdown; charset=utf-8",
+            "csv" => "text/csv; charset=utf-8",
+            "xml" => "application/xml",
+            "wasm" => "application/wasm",
+            "pdf" => "application/pdf",
+            "zip" => "application/zip",
+            "gz" => "application/gzip",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "avif" => "image/avif",
+            "svg" => "image/svg+xml",
+            "ico" => "image/x-icon",
+            "bmp" => "image/bmp",
+            "mp4" => "video/mp4",
+            "webm" => "video/webm",
+            "ogv" => "video/ogg",
+            "mp3" => "audio/mpeg",
+            "ogg" | "oga" => "audio/ogg",
+            "wav" => "audio/wav",
+            "flac" => "audio/flac",
+            "woff" => "font/woff",
+            "woff2" => "font/woff2",
+            "ttf" => "font/ttf",
+            "otf" => "font/otf",
+            _ => "application/octet-stream",
+        }
+    }
+}
+
+#[inline]
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+#[inline]
+fn escape_href(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    escaped
+}
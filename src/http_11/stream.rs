@@ -0,0 +1,198 @@
+use crate::*;
+
+/// Size of the chunks a file is read in.
+const FILE_CHUNK: usize = 64 * 1024;
+
+/// Body of a [Response] sent after the head, see [Response::set_stream] and [Response::set_file].
+#[derive(Debug)]
+pub(crate) enum BodyStream {
+    /// Chunks produced while the body is sent, its length is unknown.
+    Channel {
+        /// Receiver of the body chunks.
+        receiver: mpsc::Receiver<Bytes>,
+        /// Interval and chunk sent while no chunk is received.
+        keep_alive: Option<(Duration, Bytes)>,
+    },
+    /// Parts of a file and the bytes sent between them.
+    File {
+        /// Opened file.
+        file: File,
+        /// Position the file is read from next.
+        position: u64,
+        /// Pieces of the body left to send.
+        segments: VecDeque<Segment>,
+        /// Number of bytes left to send.
+        remaining: u64,
+    },
+}
+
+/// Piece of a file body.
+#[derive(Debug)]
+pub(crate) enum Segment {
+    /// Bytes sent as is, e.g. the part headers of `multipart/byteranges`.
+    Bytes(Bytes),
+    /// Bytes of the file.
+    Range {
+        /// Position of the first byte to send.
+        offset: u64,
+        /// Number of bytes to send.
+        length: u64,
+    },
+}
+
+/// Functions for creating and reading [BodyStream].
+impl BodyStream {
+    #[inline]
+    /// Creating a new instance of a [BodyStream] from a channel.
+    ///
+    /// # Parameters
+    /// * `receiver` - Receiver of the body chunks, the body ends when all senders are dropped.
+    /// * `keep_alive` - Interval and chunk sent while no chunk is received.
+    pub(crate) fn channel(
+        receiver: mpsc::Receiver<Bytes>,
+        keep_alive: Option<(Duration, Bytes)>,
+    ) -> Self {
+        BodyStream::Channel {
+            receiver,
+            keep_alive,
+        }
+    }
+
+    #[inline]
+    /// Creating a new instance of a [BodyStream] sending a whole file.
+    ///
+    /// # Parameters
+    /// * `file` - File just opened.
+    /// * `length` - Size of the file.
+    pub(crate) fn file(file: File, length: u64) -> Self {
+        Self::file_segments(
+            file,
+            0,
+            VecDeque::from([Segment::Range { offset: 0, length }]),
+        )
+    }
+
+    #[inline]
+    /// Creating a new instance of a [BodyStream] sending pieces of a file.
+    ///
+    /// # Parameters
+    /// * `file` - Opened file.
+    /// * `position` - Position the file is read from next.
+    /// * `segments` - Pieces of the body.
+    pub(crate) fn file_segments(file: File, position: u64, segments: VecDeque<Segment>) -> Self {
+        let remaining = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Bytes(bytes) => bytes.len() as u64,
+                Segment::Range { length, .. } => *length,
+            })
+            .sum();
+
+        BodyStream::File {
+            file,
+            position,
+            segments,
+            remaining,
+        }
+    }
+
+    #[inline]
+    /// Size of the body, if it is known before it is sent.
+    pub(crate) fn length(&self) -> Option<u64> {
+        match self {
+            BodyStream::Channel { .. } => None,
+            BodyStream::File { remaining, .. } => Some(*remaining),
+        }
+    }
+
+    #[inline]
+    /// Size of the file, if the body is a whole file not read yet.
+    pub(crate) fn file_size(&self) -> Option<u64> {
+        match self {
+            BodyStream::File { segments, .. } if segments.len() == 1 => match segments[0] {
+                Segment::Range { offset: 0, length } => Some(length),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Waits for the next non-empty chunk, [None] at the end of the body.
+    pub(crate) async fn next(&mut self) -> Option<io::Result<Bytes>> {
+        match self {
+            BodyStream::Channel {
+                receiver,
+                keep_alive,
+            } => loop {
+                let chunk = match keep_alive {
+                    Some((interval, chunk)) => {
+                        match tokio::time::timeout(*interval, receiver.recv()).await {
+                            Ok(received) => received?,
+                            Err(_) => chunk.clone(),
+                        }
+                    }
+                    None => receiver.recv().await?,
+                };
+
+                if !chunk.is_empty() {
+                    return Some(Ok(chunk));
+                }
+            },
+            BodyStream::File {
+                file,
+                position,
+                segments,
+                remaining,
+            } => loop {
+                match segments.front_mut()? {
+                    Segment::Bytes(bytes) => {
+                        let bytes = std::mem::take(bytes);
+                        segments.pop_front();
+                        *remaining -= bytes.len() as u64;
+
+                        if !bytes.is_empty() {
+                            return Some(Ok(bytes));
+                        }
+                    }
+                    Segment::Range { length: 0, .. } => {
+                        segments.pop_front();
+                    }
+                    Segment::Range { offset, length } => {
+                        let chunk = Self::read_file(file, position, *offset, *length).await;
+                        if let Ok(chunk) = &chunk {
+                            *offset += chunk.len() as u64;
+                            *length -= chunk.len() as u64;
+                            *remaining -= chunk.len() as u64;
+                        }
+                        return Some(chunk);
+                    }
+                }
+            },
+        }
+    }
+
+    #[inline]
+    /// Reads the next chunk of at most `length` bytes at `offset`.
+    async fn read_file(
+        file: &mut File,
+        position: &mut u64,
+        offset: u64,
+        length: u64,
+    ) -> io::Result<Bytes> {
+        if *position != offset {
+            file.seek(SeekFrom::Start(offset)).await?;
+            *position = offset;
+        }
+
+        let mut chunk = BytesMut::zeroed(length.min(FILE_CHUNK as u64) as usize);
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        chunk.truncate(read);
+
+        *position += read as u64;
+        Ok(chunk.freeze())
+    }
+}
@@ -0,0 +1,357 @@
+use crate::http_11::metrics;
+use crate::http_11::response::header_line;
+use crate::http_11::server::{with_timeout, Peer};
+use crate::http_11::stream::BodyStream;
+use crate::rewind::Rewind;
+use crate::shared::Shared;
+use crate::*;
+
+/// Connection preface every HTTP/2 client sends first.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+#[inline]
+/// Reads until the connection preface is received or ruled out.
+/// Returns whether the client speaks HTTP/2 and the stream with the read bytes put back.
+pub(crate) async fn sniff<S: AsyncRead + Unpin>(mut stream: S) -> io::Result<(bool, Rewind<S>)> {
+    let mut read = BytesMut::with_capacity(PREFACE.len());
+
+    while !read.starts_with(PREFACE) && PREFACE.starts_with(&read) {
+        if stream.read_buf(&mut read).await? == 0 {
+            break;
+        }
+    }
+
+    Ok((
+        read.starts_with(PREFACE),
+        Rewind::new(read.freeze(), stream),
+    ))
+}
+
+/// Functions for serving HTTP/2 connections with [HttpServer].
+impl HttpServer {
+    #[inline]
+    /// Serves the streams of the connection until the client closes it, or until no stream
+    /// is open for [Timeouts::keep_alive]. At most [Limits::streams] streams are handled at once,
+    /// streams whose handler is still running count even after the client reset them.
+    /// Once the connection is closed, the streams still running are awaited before returning.
+    pub(crate) async fn serve_h2<S, FutW, R>(
+        self: &Arc<Self>,
+        stream: S,
+        peer: Peer,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<(), (ServerError, ErrorPhase)>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        let handshake = server::Builder::new()
+            .max_header_list_size(self.limits.header_bytes.min(u32::MAX as usize) as u32)
+            .max_concurrent_streams(self.limits.streams)
+            .handshake::<_, Bytes>(stream);
+        let mut connection = with_timeout(self.timeouts.head, handshake, ServerError::HeadTimeout)
+            .await
+            .and_then(|connection| connection.map_err(ServerError::Http2))
+            .map_err(|err| (err, ErrorPhase::Read))?;
+
+        let mut streams = JoinSet::new();
+        let mut closing = false;
+
+        loop {
+            let idle = self
+                .timeouts
+                .keep_alive
+                .filter(|_| streams.is_empty() && !closing);
+            let idle = async move {
+                match idle {
+                    Some(idle) => tokio::time::sleep(idle).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                accepted = connection.accept() => match accepted {
+                    Some(Ok((_, mut respond))) if streams.len() >= self.limits.streams as usize => {
+                        respond.send_reset(h2::Reason::REFUSED_STREAM);
+                    }
+                    Some(Ok((request, respond))) => {
+                        let stream = self
+                            .clone()
+                            .serve_h2_stream(request, respond, peer.clone(), work_fn);
+                        #[cfg(feature = "tracing")]
+                        let stream = tracing::Instrument::in_current_span(stream);
+
+                        streams.spawn(stream);
+                    }
+                    Some(Err(err)) => return Err((ServerError::Http2(err), ErrorPhase::Read)),
+                    None => break,
+                },
+                Some(joined) = streams.join_next() => {
+                    if let Ok(Err((err, phase))) = joined {
+                        self.report(&err, Some(peer.addr), phase);
+                    }
+                }
+                () = idle => {
+                    connection.graceful_shutdown();
+                    closing = true;
+                }
+            }
+        }
+
+        while let Some(joined) = streams.join_next().await {
+            if let Ok(Err((err, phase))) = joined {
+                self.report(&err, Some(peer.addr), phase);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    /// Reads the request of a stream, runs the handler and sends its response.
+    async fn serve_h2_stream<FutW, R>(
+        self: Arc<Self>,
+        request: http::Request<RecvStream>,
+        mut respond: SendResponse<Bytes>,
+        peer: Peer,
+        work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+    ) -> Result<(), (ServerError, ErrorPhase)>
+    where
+        FutW: Future<Output = R> + Send + Sync + 'static,
+        R: IntoResponse + 'static,
+    {
+        let (parts, mut body) = request.into_parts();
+        let request = match self.read_h2_request(parts, &mut body, &peer).await {
+            Ok(request) => request,
+            Err(err) => {
+                if err.status_code().is_some() {
+                    let sent = self
+                        .send_h2_response(&mut respond, &err.response(), false)
+                        .await;
+                    if let Ok((bytes, body)) = sent {
+                        self.finish_rejected(peer.addr, &err, bytes, body).await;
+                    }
+                }
+                return Err((err, ErrorPhase::Read));
+            }
+        };
+
+        let head = request.method == Method::HEAD;
+        let served = self.begin(&request, metrics::decoded_size(&request), peer.addr);
+
+        let response = match self.respond(request, &served, work_fn).await {
+            Ok(response) => response,
+            Err((err, response)) => {
+                if let Some(response) = response {
+                    let _ = self.send_h2_response(&mut respond, &response, false).await;
+                }
+                return Err((err, ErrorPhase::Handle));
+            }
+        };
+
+        let (bytes, body) = self
+            .send_h2_response(&mut respond, &response, head)
+            .await
+            .map_err(|err| (err, ErrorPhase::Write))?;
+
+        self.finish(served, response.status_code, bytes, body).await;
+
+        Ok(())
+    }
+
+    #[inline]
+    /// Converts the request of a stream into a [Request], reading the body within [Limits::body].
+    /// The body stream is left to the caller, dropping it before responding resets the stream.
+    async fn read_h2_request(
+        &self,
+        parts: http::request::Parts,
+        body: &mut RecvStream,
+        peer: &Peer,
+    ) -> Result<Request, ServerError> {
+        if parts.headers.len() > self.limits.header_count {
+            return Err(ServerError::TooManyHeaders);
+        }
+
+        let length = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|val| val.to_str().ok())
+            .and_then(|val| val.parse::<usize>().ok());
+        if length.is_some_and(|length| length > self.limits.body) {
+            return Err(ServerError::BodyTooLarge);
+        }
+
+        let read = async {
+            let mut data = Vec::with_capacity(length.unwrap_or_default());
+            while let Some(chunk) = body.data().await {
+                let chunk = chunk.map_err(ServerError::Http2)?;
+                let _ = body.flow_control().release_capacity(chunk.len());
+
+                if data.len() + chunk.len() > self.limits.body {
+                    return Err(ServerError::BodyTooLarge);
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(data)
+        };
+        let body = with_timeout(self.timeouts.body, read, ServerError::BodyTimeout).await??;
+
+        let mut headers = parts.headers;
+        if let (false, Some(authority)) = (headers.contains_key(HOST), parts.uri.authority()) {
+            headers.insert(HOST, HeaderValue::from_str(authority.as_str())?);
+        }
+
+        let cookies = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|val| val.to_str().ok())
+            .collect::<Vec<_>>();
+        if cookies.len() > 1 {
+            let cookies = HeaderValue::from_str(&cookies.join("; "))?;
+            headers.insert(COOKIE, cookies);
+        }
+
+        let url = match parts.uri.path_and_query() {
+            Some(path) => Uri::from(path.clone()),
+            None => parts.uri.clone(),
+        };
+
+        let mut request = Request {
+            #[cfg(feature = "get_stream")]
+            socket_addr: peer.addr,
+            method: parts.method,
+            url,
+            version: Version::HTTP_2,
+
+            cookies: HashMap::new(),
+            headers,
+            body,
+            #[cfg(feature = "tls")]
+            tls: None,
+        };
+        request.parse_cookies();
+
+        Ok(peer.attach(request))
+    }
+
+    #[inline]
+    /// Sends the response, or its page from [DEF_PAGES], without the HTTP/1 connection headers.
+    /// A streamed body is sent as data frames until it ends, waiting for the client to take
+    /// each chunk within [Timeouts::write].
+    /// Returns the size of the serialized response and of its body.
+    async fn send_h2_response(
+        &self,
+        respond: &mut SendResponse<Bytes>,
+        response: &Response,
+        head: bool,
+    ) -> Result<(usize, usize), ServerError> {
+        let (head_response, body, size, stream) = match DEF_PAGES.get(&response.status_code) {
+            Some(page) => {
+                let (head_response, body, size) = Self::h2_head(&page, None)?;
+                (head_response, body, size, None)
+            }
+            None => {
+                let stream = response.stream.as_ref().and_then(Shared::take);
+                let length = stream.as_ref().map(BodyStream::length);
+                let (head_response, body, size) = Self::h2_head(response, length)?;
+                (head_response, body, size, stream)
+            }
+        };
+        let end = head || (body.is_empty() && stream.is_none());
+
+        let mut send = respond
+            .send_response(head_response, end)
+            .map_err(ServerError::Http2)?;
+        if end {
+            return Ok((size, body.len()));
+        }
+
+        match stream {
+            Some(mut stream) => {
+                let mut streamed = 0;
+                while let Some(chunk) = stream.next().await {
+                    let chunk = chunk.map_err(ServerError::Read)?;
+                    streamed += chunk.len();
+                    let data = Self::send_data(&mut send, chunk);
+                    with_timeout(self.timeouts.write, data, ServerError::WriteTimeout).await??;
+                }
+                send.send_data(Bytes::new(), true)
+                    .map_err(ServerError::Http2)?;
+
+                Ok((size + streamed, streamed))
+            }
+            None => {
+                let len = body.len();
+                send.send_data(body, true).map_err(ServerError::Http2)?;
+
+                Ok((size, len))
+            }
+        }
+    }
+
+    #[inline]
+    /// Builds the head of a response from its header lines without the HTTP/1 connection
+    /// headers, with the length of the streamed body if there is one.
+    /// Returns the head, the body held in memory and the size of the serialized response.
+    fn h2_head(
+        response: &Response,
+        stream: Option<Option<u64>>,
+    ) -> Result<(http::Response<()>, Bytes, usize), ServerError> {
+        let (head, body) = response.parts();
+
+        let mut builder = http::Response::builder().status(response.status_code);
+        let mut has_length = false;
+        for line in head
+            .iter()
+            .flat_map(|part| part.split(|byte| *byte == b'\n'))
+        {
+            let Some((name, value)) = header_line(line) else {
+                continue;
+            };
+            let (Ok(name), Ok(value)) =
+                (HeaderName::from_bytes(name), HeaderValue::from_bytes(value))
+            else {
+                continue;
+            };
+
+            match name.as_str() {
+                "connection" | "keep-alive" | "proxy-connection" | "transfer-encoding"
+                | "upgrade" => continue,
+                "content-length" if stream.is_some() => continue,
+                "content-length" => has_length = true,
+                _ => {}
+            }
+            builder = builder.header(name, value);
+        }
+        match stream {
+            Some(Some(length)) => builder = builder.header(CONTENT_LENGTH, length),
+            Some(None) => {}
+            None if !has_length => builder = builder.header(CONTENT_LENGTH, body.len()),
+            None => {}
+        }
+
+        let head_response = builder.body(()).map_err(|_| ServerError::InvalidHeader)?;
+        Ok((
+            head_response,
+            Bytes::copy_from_slice(body),
+            response.wire_len(),
+        ))
+    }
+
+    #[inline]
+    /// Sends a chunk as data frames once the flow-control window of the stream allows it,
+    /// so a large body is not buffered while the client reads slowly.
+    async fn send_data(send: &mut SendStream<Bytes>, mut chunk: Bytes) -> Result<(), ServerError> {
+        while !chunk.is_empty() {
+            send.reserve_capacity(chunk.len());
+            let capacity = poll_fn(|cx| send.poll_capacity(cx))
+                .await
+                .ok_or(ServerError::StreamClosed)?
+                .map_err(ServerError::Http2)?;
+
+            let data = chunk.split_to(capacity.min(chunk.len()));
+            send.send_data(data, false).map_err(ServerError::Http2)?;
+        }
+        Ok(())
+    }
+}
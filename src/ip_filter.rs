@@ -0,0 +1,259 @@
+use crate::*;
+
+/// IPv4 or IPv6 address range in CIDR notation, e.g. `10.0.0.0/8` or `2001:db8::/32`.
+/// An address without a prefix matches only itself. IPv4-mapped IPv6 ranges of at least
+/// `/96`, e.g. `::ffff:10.0.0.0/104`, are stored as the IPv4 range they cover.
+///
+/// # Examples
+/// ```
+/// use maker_web::Cidr;
+///
+/// let cidr: Cidr = "192.168.0.0/16".parse().unwrap();
+///
+/// assert!(cidr.contains("192.168.10.1".parse().unwrap()));
+/// assert!(!cidr.contains("10.0.0.1".parse().unwrap()));
+/// assert!("::1".parse::<Cidr>().unwrap().contains("::1".parse().unwrap()));
+/// assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = ServerError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || ServerError::InvalidIpRule(value.to_string());
+
+        let (addr, prefix) = match value.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid())?;
+
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+
+        match addr.to_canonical() {
+            IpAddr::V4(mapped) if addr.is_ipv6() && prefix >= 96 => Ok(Cidr {
+                addr: IpAddr::V4(mapped),
+                prefix: prefix - 96,
+            }),
+            _ => Ok(Cidr { addr, prefix }),
+        }
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Functions for matching [Cidr].
+impl Cidr {
+    #[inline]
+    /// Whether the address is inside the range.
+    /// IPv4-mapped IPv6 addresses are matched as IPv4.
+    ///
+    /// # Parameters
+    /// * `ip` - Address to check.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V4(ip)) => self.contains_v6(net, ip.to_ipv6_mapped()),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => self.contains_v6(net, ip),
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn contains_v6(&self, net: Ipv6Addr, ip: Ipv6Addr) -> bool {
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(self.prefix))
+            .unwrap_or(0);
+        u128::from(net) & mask == u128::from(ip) & mask
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Rules {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+/// Client filter with CIDR allow and deny lists.
+///
+/// A client is rejected if it matches a deny rule, or if the allow list is not empty and
+/// it matches no allow rule. The rules can be replaced at runtime, the running server picks
+/// them up for the next connection.
+///
+/// Rules files contain one `allow <cidr>` or `deny <cidr>` per line, `#` starts a comment.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{CleanServer, IpFilter};
+/// use std::{sync::Arc, time::Duration};
+/// use tokio::net::{TcpListener, TcpStream};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let filter = Arc::new(IpFilter::from_file("ip_rules.txt").await.unwrap());
+///
+///     let reload = filter.clone();
+///     tokio::spawn(async move {
+///         loop {
+///             tokio::time::sleep(Duration::from_secs(60)).await;
+///             let _ = reload.load("ip_rules.txt").await;
+///         }
+///     });
+///
+///     CleanServer::from_fn(|server| server.set_ip_filter(filter.clone()))
+///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+///         .await;
+/// }
+///
+/// async fn work(mut stream: TcpStream) {}
+/// ```
+#[derive(Debug, Default)]
+pub struct IpFilter {
+    rules: RwLock<Rules>,
+}
+
+/// Functions for creating and reloading [IpFilter].
+impl IpFilter {
+    #[inline]
+    /// Creating a new instance of a [IpFilter] that allows every client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    /// Creating a new instance of a [IpFilter] from the rules text.
+    ///
+    /// # Parameters
+    /// * `rules` - One `allow <cidr>` or `deny <cidr>` per line.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::IpFilter;
+    ///
+    /// let filter = IpFilter::from_rules("
+    ///     allow 10.0.0.0/8 # internal network
+    ///     allow fd00::/8
+    ///     deny 10.0.13.0/24
+    /// ").unwrap();
+    ///
+    /// assert!(filter.allows("10.1.2.3".parse().unwrap()));
+    /// assert!(filter.allows("::ffff:10.1.2.3".parse().unwrap()));
+    /// assert!(!filter.allows("10.0.13.7".parse().unwrap()));
+    /// assert!(!filter.allows("8.8.8.8".parse().unwrap()));
+    /// ```
+    pub fn from_rules(rules: &str) -> Result<Self, ServerError> {
+        let filter = Self::new();
+        filter.set_rules(rules)?;
+        Ok(filter)
+    }
+
+    #[inline]
+    /// Creating a new instance of a [IpFilter] from a rules file.
+    ///
+    /// # Parameters
+    /// * `path` - Path to the rules file.
+    pub async fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ServerError> {
+        let filter = Self::new();
+        filter.load(path).await?;
+        Ok(filter)
+    }
+
+    #[inline]
+    /// Replaces the rules with the ones from the text.
+    /// On error the previous rules are kept.
+    ///
+    /// # Parameters
+    /// * `rules` - One `allow <cidr>` or `deny <cidr>` per line.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::IpFilter;
+    ///
+    /// let filter = IpFilter::new();
+    /// assert!(filter.allows("1.2.3.4".parse().unwrap()));
+    ///
+    /// filter.set_rules("deny 1.2.3.0/24").unwrap();
+    /// assert!(!filter.allows("1.2.3.4".parse().unwrap()));
+    ///
+    /// assert!(filter.set_rules("block 1.2.3.4").is_err());
+    /// assert!(!filter.allows("1.2.3.4".parse().unwrap()));
+    /// ```
+    pub fn set_rules(&self, rules: &str) -> Result<(), ServerError> {
+        let mut parsed = Rules::default();
+
+        for line in rules.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match line.split_once(char::is_whitespace) {
+                Some(("allow", cidr)) => parsed.allow.push(cidr.parse()?),
+                Some(("deny", cidr)) => parsed.deny.push(cidr.parse()?),
+                _ => return Err(ServerError::InvalidIpRule(line.to_string())),
+            }
+        }
+
+        *self.rules.write().unwrap_or_else(|err| err.into_inner()) = parsed;
+        Ok(())
+    }
+
+    #[inline]
+    /// Replaces the rules with the ones from a file.
+    /// On error the previous rules are kept.
+    ///
+    /// # Parameters
+    /// * `path` - Path to the rules file.
+    pub async fn load<P: AsRef<Path>>(&self, path: P) -> Result<(), ServerError> {
+        let rules = fs::read_to_string(path)
+            .await
+            .map_err(ServerError::OpeningFile)?;
+        self.set_rules(&rules)
+    }
+}
+
+/// Functions for checking clients with [IpFilter].
+impl IpFilter {
+    #[inline]
+    /// Whether the address passes the rules.
+    ///
+    /// # Parameters
+    /// * `ip` - Client address.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let rules = self.rules.read().unwrap_or_else(|err| err.into_inner());
+
+        !rules.deny.iter().any(|cidr| cidr.contains(ip))
+            && (rules.allow.is_empty() || rules.allow.iter().any(|cidr| cidr.contains(ip)))
+    }
+
+    #[inline]
+    /// Whether the client passes the rules.
+    /// Meant to be called from the `check_fn` of [HttpServer] and [CleanServer].
+    ///
+    /// # Parameters
+    /// * `addr` - Client socket address.
+    pub fn check(&self, addr: SocketAddr) -> bool {
+        self.allows(addr.ip())
+    }
+}
@@ -41,12 +41,29 @@
 //!
 //! # Supported Protocols
 //! * `Without protocol`: [CleanServer]
-//! * `HTTP`: [HttpServer]
+//! * `HTTP/1.1` and `HTTP/2`: [HttpServer]
+//! * `Upgrade` and `CONNECT` tunnels after an `HTTP/1.1` handshake: [Response::set_upgrade]
+//! * `Server-Sent Events` over `HTTP/1.1` and `HTTP/2`: [Response::from_events]
+//! * `Static files` from a directory: [StaticFiles]
 //!
 //! # Feature flags
-//! * `get_stream`: Adds a `socket_addr` field to the [Request].
-//! * `check_stream`: Allows you to implement custom security measures by enabling address
-//! verification logic in [HttpServer::launch].
+//! * `get_stream` (default): Adds a `socket_addr` field to the [Request].
+//! * `check_stream` (default): Allows you to implement custom security measures by adding the
+//!   `launch_with_check` and `run_with_check` functions to [HttpServer] and [CleanServer],
+//!   which verify the client address before the connection is served.
+//! * `json`: Adds the `Json` response body and [IntoResponse] for `serde_json::Value`.
+//! * `tracing`: Routes all crate messages through `tracing` and adds spans per connection
+//!   and per request (method, path, status, latency, peer address).
+//! * `tls`: Adds [TlsConfig] for serving HTTPS and TLS with `rustls`, optionally verifying
+//!   client certificates, and the `tls` field with the SNI name and the client certificate
+//!   to the [Request].
+//! * `http2`: Adds `set_http2` to [HttpServer] for serving HTTP/2, negotiated with ALPN under
+//!   TLS or started with the prior-knowledge preface on cleartext connections.
+//! * `websocket`: Adds [WebSocket] for upgrading HTTP/1.1 requests to WebSocket connections.
+//! * `sendfile`: Sends file bodies over plain TCP connections with `sendfile` on Linux,
+//!   without copying them through the server.
+//! * `console`: Writes crate messages to stderr when `tracing` is disabled.
+//!   Without both features the crate prints nothing.
 
 /*
 #![feature(async_fn_in_trait)]
@@ -60,6 +77,9 @@
 #![deny(nonstandard_style)]
 */
 
+#[macro_use]
+mod logging;
+
 /// Clean server.
 pub mod clean {
     /// Server.
@@ -67,44 +87,156 @@ pub mod clean {
 }
 /// HTTP/1.1 server.
 pub mod http_11 {
+    /// Access log.
+    pub mod access_log;
+    /// Conditional requests.
+    pub(crate) mod conditional;
+    /// Conversion into response.
+    pub mod into_response;
+    /// Prometheus-style metrics.
+    pub mod metrics;
+    /// Safe mapping of URL paths to files.
+    pub mod path_resolver;
+    /// Byte range requests.
+    pub(crate) mod range;
     /// Request.
     pub mod request;
     /// Response.
     pub mod response;
     /// Server.
     pub mod server;
+    /// Server-Sent Events.
+    pub mod sse;
+    /// Static file serving.
+    pub mod static_files;
+    /// Streamed response bodies.
+    pub(crate) mod stream;
 }
+/// Connection limits.
+pub mod connections;
 /// Server error file.
 pub mod errors;
+#[cfg(feature = "http2")]
+/// HTTP/2 connections.
+mod http_2;
+/// IP allow and deny lists.
+pub mod ip_filter;
+/// Per-IP rate limiting.
+pub mod rate_limit;
+/// Replaying of bytes already read from a stream.
+mod rewind;
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+/// Zero-copy file bodies.
+mod sendfile;
+/// Values shared by the clones of a response.
+mod shared;
+#[cfg(feature = "tls")]
+/// TLS termination.
+pub mod tls;
+/// Taking over connections after a response.
+pub mod upgrade;
+#[cfg(feature = "websocket")]
+/// WebSocket connections.
+pub mod websocket;
 
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     convert::AsRef,
+    fmt,
     future::Future,
     marker::{Copy, Send, Sync, Unpin},
-    net::SocketAddr,
-    path::Path,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    pin::Pin,
     str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+#[cfg(feature = "tls")]
+use {
+    crate::http_11::server::with_timeout,
+    rustls::{
+        crypto::CryptoProvider,
+        pki_types::{
+            pem::{self, PemObject},
+            CertificateDer, PrivateKeyDer,
+        },
+        server::{
+            danger::ClientCertVerifier, ClientHello, ResolvesServerCert, WebPkiClientVerifier,
+        },
+        sign::CertifiedKey,
+        RootCertStore, ServerConfig, ServerConnection,
+    },
+    std::convert::TryFrom,
+    tokio_rustls::TlsAcceptor,
+    x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer},
+};
+#[cfg(feature = "websocket")]
+use {
+    base64::{engine::general_purpose::STANDARD, Engine},
+    http::header::{SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE},
+    sha1_smol::Sha1,
 };
 use {
     bytes::{Bytes, BytesMut},
     dashmap::DashMap,
     http::{
-        header::{CONTENT_LENGTH, COOKIE},
-        HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri,
+        header::{
+            CONNECTION, CONTENT_LENGTH, COOKIE, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+            IF_RANGE, IF_UNMODIFIED_SINCE, RANGE, REFERER, TRANSFER_ENCODING, USER_AGENT,
+        },
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri, Version,
     },
     once_cell::sync::Lazy,
     thiserror::Error,
     tokio::{
         fs::{self, File},
-        io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf},
+        io::{
+            self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite,
+            AsyncWriteExt, BufReader, ReadBuf, SeekFrom,
+        },
         net::{TcpListener, TcpStream},
+        sync::{mpsc, OwnedSemaphorePermit, Semaphore},
+    },
+};
+#[cfg(feature = "http2")]
+use {
+    h2::{
+        server::{self, SendResponse},
+        RecvStream, SendStream,
     },
+    http::header::HOST,
+    std::future::poll_fn,
+    tokio::task::JoinSet,
 };
 
 pub use crate::clean::server::CleanServer;
-pub use crate::errors::ServerError;
-pub use crate::http_11::request::Request;
+pub use crate::connections::{ConnectionLimit, Saturation};
+pub use crate::errors::{ErrorHook, ErrorPhase, ServerError};
+pub use crate::http_11::access_log::{AccessEntry, AccessLog, LogFormat, Rotation};
+pub use crate::http_11::into_response::IntoResponse;
+#[cfg(feature = "json")]
+pub use crate::http_11::into_response::Json;
+pub use crate::http_11::metrics::Metrics;
+pub use crate::http_11::path_resolver::PathResolver;
+pub use crate::http_11::request::{Limits, Request};
 pub use crate::http_11::response::Response;
 pub use crate::http_11::server::HttpServer;
-pub use crate::http_11::server::DEF_PAGES;
+pub use crate::http_11::server::{Timeouts, DEF_PAGES};
+pub use crate::http_11::sse::{Event, EventSender};
+pub use crate::http_11::static_files::StaticFiles;
+pub use crate::ip_filter::{Cidr, IpFilter};
+pub use crate::rate_limit::RateLimiter;
+#[cfg(feature = "tls")]
+pub use crate::tls::{ClientAuth, TlsConfig, TlsInfo};
+pub use crate::upgrade::Upgraded;
+#[cfg(feature = "websocket")]
+pub use crate::websocket::{CloseFrame, Message, WebSocket};
+#[cfg(feature = "tls")]
+pub use tokio_rustls::{rustls, server::TlsStream};
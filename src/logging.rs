@@ -0,0 +1,12 @@
+/// Writes a crate message through `tracing` if the feature is enabled,
+/// to the console with the `console` feature, or discards it.
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+        #[cfg(all(feature = "console", not(feature = "tracing")))]
+        eprintln!($($arg)+);
+        #[cfg(not(any(feature = "tracing", feature = "console")))]
+        let _ = format_args!($($arg)+);
+    }};
+}
@@ -10,7 +10,8 @@ async fn main() {
         TcpListener::bind("127.0.0.1:80").await.unwrap(),
         work,
         check,
-    ).await;
+    )
+    .await;
 }
 
 #[inline]
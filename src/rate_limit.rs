@@ -0,0 +1,276 @@
+use crate::*;
+
+/// Largest `Retry-After` sent to a limited client, in seconds.
+const MAX_RETRY_AFTER: u64 = 86_400;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Per-IP token bucket rate limiter.
+///
+/// Every client address gets a bucket of `burst` tokens refilled at `per_second` tokens per
+/// second, each request takes one token. Buckets idle for longer than the eviction time
+/// are dropped while the limiter is used once they have refilled to `burst`, so a client
+/// never regains more tokens by waiting for its bucket to be dropped.
+///
+/// # Examples
+///
+/// As a check function (`check_stream` feature), rejected clients are disconnected:
+/// ```no_run
+/// use maker_web::{HttpServer, RateLimiter, Request, Response};
+/// use once_cell::sync::Lazy;
+/// use std::net::SocketAddr;
+/// use tokio::net::TcpListener;
+///
+/// static LIMITER: Lazy<RateLimiter> = Lazy::new(|| RateLimiter::new(20, 5.0));
+///
+/// # #[cfg(not(feature = "check_stream"))]
+/// # fn main() {}
+/// # #[cfg(feature = "check_stream")]
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::launch_with_check(
+///         TcpListener::bind("127.0.0.1:80").await.unwrap(),
+///         work,
+///         check,
+///     ).await;
+/// }
+///
+/// async fn check(addr: SocketAddr) -> bool {
+///     LIMITER.check(addr)
+/// }
+/// async fn work(_request: Request) -> Response {
+///     Response::from_body("All Good :)")
+/// }
+/// ```
+/// As middleware of [HttpServer], rejected requests are answered with 429:
+/// ```no_run
+/// use maker_web::{HttpServer, RateLimiter, Request, Response};
+/// use std::sync::Arc;
+/// use tokio::net::TcpListener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::from_fn(|server| server.set_rate_limiter(Arc::new(RateLimiter::new(20, 5.0))))
+///         .run(TcpListener::bind("127.0.0.1:80").await.unwrap(), work)
+///         .await;
+/// }
+///
+/// async fn work(_request: Request) -> Response {
+///     Response::from_body("All Good :)")
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    burst: f64,
+    per_second: f64,
+    evict_after: Duration,
+    buckets: DashMap<IpAddr, Bucket>,
+    created: Instant,
+    last_sweep: AtomicU64,
+}
+
+/// Functions for creating and configuring [RateLimiter].
+impl RateLimiter {
+    #[inline]
+    /// Creating a new instance of a [RateLimiter].
+    /// Idle buckets are evicted after one minute.
+    ///
+    /// # Parameters
+    /// * `burst` - Number of requests a client can make at once.
+    /// * `per_second` - Number of requests per second a client regains. Zero, negative
+    ///   and NaN values mean a client never regains tokens after the burst.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::RateLimiter;
+    ///
+    /// let limiter = RateLimiter::new(2, 1.0);
+    /// let ip = "10.0.0.1".parse().unwrap();
+    ///
+    /// assert!(limiter.acquire(ip).is_ok());
+    /// assert!(limiter.acquire(ip).is_ok());
+    /// assert!(limiter.acquire(ip).is_err());
+    /// assert!(limiter.acquire("10.0.0.2".parse().unwrap()).is_ok());
+    ///
+    /// let limiter = RateLimiter::new(1, f64::NAN);
+    /// assert!(limiter.acquire(ip).is_ok());
+    /// assert_eq!(limiter.acquire(ip), Err(std::time::Duration::MAX));
+    /// ```
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        RateLimiter {
+            burst: f64::from(burst),
+            per_second: if per_second > 0.0 {
+                per_second.min(f64::MAX)
+            } else {
+                0.0
+            },
+            evict_after: Duration::from_secs(60),
+            buckets: DashMap::new(),
+            created: Instant::now(),
+            last_sweep: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    /// Sets after how long without requests a client's bucket is dropped.
+    /// A bucket is only dropped once it has refilled to `burst`, never with a zero rate.
+    ///
+    /// # Parameters
+    /// * `evict_after` - Idle time before eviction.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::RateLimiter;
+    /// use std::time::Duration;
+    ///
+    /// let mut limiter = RateLimiter::new(10, 1000.0);
+    /// limiter.set_eviction(Duration::from_millis(10));
+    ///
+    /// limiter.acquire("10.0.0.1".parse().unwrap()).unwrap();
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// limiter.acquire("10.0.0.2".parse().unwrap()).unwrap();
+    ///
+    /// assert_eq!(limiter.len(), 1);
+    /// ```
+    pub fn set_eviction(&mut self, evict_after: Duration) {
+        self.evict_after = evict_after;
+    }
+}
+
+/// Functions for limiting clients with [RateLimiter].
+impl RateLimiter {
+    #[inline]
+    /// Takes a token from the client's bucket.
+    /// Returns the time until a token is available if the bucket is empty.
+    ///
+    /// # Parameters
+    /// * `ip` - Client address.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::RateLimiter;
+    /// use std::time::Duration;
+    ///
+    /// let limiter = RateLimiter::new(1, 0.5);
+    /// let ip = "::1".parse().unwrap();
+    ///
+    /// assert!(limiter.acquire(ip).is_ok());
+    ///
+    /// let retry_after = limiter.acquire(ip).unwrap_err();
+    /// assert!(retry_after > Duration::from_millis(1900) && retry_after <= Duration::from_secs(2));
+    /// ```
+    pub fn acquire(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        self.sweep(now);
+
+        let mut bucket = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            last: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.per_second;
+            Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX))
+        }
+    }
+
+    #[inline]
+    /// Takes a token for the client, returns `false` if it is over the limit.
+    /// Meant to be called from the `check_fn` of [HttpServer] and [CleanServer].
+    ///
+    /// # Parameters
+    /// * `addr` - Client socket address.
+    pub fn check(&self, addr: SocketAddr) -> bool {
+        self.acquire(addr.ip()).is_ok()
+    }
+
+    #[inline]
+    /// Number of clients currently tracked.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::RateLimiter;
+    ///
+    /// let limiter = RateLimiter::new(5, 1.0);
+    /// limiter.acquire("10.0.0.1".parse().unwrap()).unwrap();
+    ///
+    /// assert_eq!(limiter.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[inline]
+    /// Whether no client is currently tracked.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    #[inline]
+    /// Response answered to a client over the limit: 429 with `Retry-After`.
+    ///
+    /// # Parameters
+    /// * `retry_after` - Time until the client regains a token, sent as at most one day.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::RateLimiter;
+    /// use http::StatusCode;
+    /// use std::time::Duration;
+    ///
+    /// let response = RateLimiter::response(Duration::from_millis(1500));
+    ///
+    /// assert_eq!(response.status_code, StatusCode::TOO_MANY_REQUESTS);
+    /// assert_eq!(response.headers.as_ref(), b"Retry-After: 2\r\n");
+    ///
+    /// let response = RateLimiter::response(Duration::MAX);
+    /// assert_eq!(response.headers.as_ref(), b"Retry-After: 86400\r\n");
+    /// ```
+    pub fn response(retry_after: Duration) -> Response {
+        let mut response = ServerError::RateLimited.response();
+        let secs = retry_after
+            .as_secs()
+            .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+
+        response.add_header(
+            "Retry-After",
+            itoa::Buffer::new().format(secs.clamp(1, MAX_RETRY_AFTER)),
+        );
+        response
+    }
+
+    #[inline]
+    /// Drops idle buckets that have refilled to `burst`, at most once per eviction time.
+    fn sweep(&self, now: Instant) {
+        let evict_ms = self.evict_after.as_millis() as u64;
+        let now_ms = now.saturating_duration_since(self.created).as_millis() as u64;
+        let last = self.last_sweep.load(Ordering::Relaxed);
+
+        if now_ms.saturating_sub(last) < evict_ms
+            || self
+                .last_sweep
+                .compare_exchange(last, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+
+        self.buckets.retain(|_, bucket| {
+            let idle = now.saturating_duration_since(bucket.last);
+            idle < self.evict_after
+                || self.per_second == 0.0
+                || bucket.tokens + idle.as_secs_f64() * self.per_second < self.burst
+        });
+    }
+}
@@ -0,0 +1,84 @@
+use crate::*;
+
+/// Largest number of bytes passed to one `sendfile` call.
+const MAX_SEND: u64 = 1 << 30;
+
+#[inline]
+/// Sends part of a file to a socket with `sendfile`, without copying it through user space.
+/// Runs on a blocking thread with a duplicate of the socket and returns the number of bytes sent.
+///
+/// # Parameters
+/// * `socket` - Plain TCP socket of the connection.
+/// * `file` - Opened file, shared by the ranges of a body.
+/// * `offset` - Position of the first byte to send.
+/// * `length` - Number of bytes to send.
+/// * `timeout` - Longest wait for the socket to accept more data.
+pub(crate) async fn send_file(
+    socket: RawFd,
+    file: Arc<std::fs::File>,
+    offset: u64,
+    length: u64,
+    timeout: Option<Duration>,
+) -> io::Result<u64> {
+    // SAFETY: the socket is open while the connection is served, the duplicate is owned here.
+    let socket = match unsafe { libc::dup(socket) } {
+        -1 => return Err(io::Error::last_os_error()),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) },
+    };
+    let timeout = timeout.map_or(-1, |timeout| {
+        timeout.as_millis().min(i32::MAX as u128) as libc::c_int
+    });
+
+    tokio::task::spawn_blocking(move || {
+        let mut position = offset as libc::off_t;
+        let mut sent = 0;
+
+        while sent < length {
+            let count = (length - sent).min(MAX_SEND) as usize;
+            // SAFETY: both descriptors are owned by this closure.
+            let written = unsafe {
+                libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut position, count)
+            };
+
+            match written {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                written if written > 0 => sent += written as u64,
+                _ => {
+                    let err = io::Error::last_os_error();
+                    match err.kind() {
+                        io::ErrorKind::Interrupted => {}
+                        io::ErrorKind::WouldBlock => wait_writable(&socket, timeout)?,
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+
+        Ok(sent)
+    })
+    .await?
+}
+
+#[inline]
+/// Blocks until the socket accepts more data, [io::ErrorKind::TimedOut] after `timeout` ms.
+fn wait_writable(socket: &OwnedFd, timeout: libc::c_int) -> io::Result<()> {
+    let mut poll = libc::pollfd {
+        fd: socket.as_raw_fd(),
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+
+    loop {
+        // SAFETY: `poll` points to one valid `pollfd`.
+        match unsafe { libc::poll(&mut poll, 1, timeout) } {
+            0 => return Err(io::ErrorKind::TimedOut.into()),
+            ready if ready > 0 => return Ok(()),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::*;
+
+/// Value shared by the clones of a [Response] and taken out by the server at most once.
+/// Clones compare by identity.
+pub(crate) struct Shared<T>(Arc<Mutex<Option<T>>>);
+
+/// Functions for creating and taking [Shared].
+impl<T> Shared<T> {
+    #[inline]
+    /// Creating a new instance of a [Shared].
+    ///
+    /// # Parameters
+    /// * `value` - Value to share.
+    pub(crate) fn new(value: T) -> Self {
+        Self(Arc::new(Mutex::new(Some(value))))
+    }
+
+    #[inline]
+    /// Takes the value out, [None] if a clone already took it.
+    pub(crate) fn take(&self) -> Option<T> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).take()
+    }
+
+    #[inline]
+    /// Applies `f` to the value, [None] if a clone already took it.
+    pub(crate) fn map<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .as_ref()
+            .map(f)
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Shared").finish_non_exhaustive()
+    }
+}
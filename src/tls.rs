@@ -0,0 +1,564 @@
+use crate::*;
+
+/// TLS settings of [HttpServer] and [CleanServer]: certificate chain, private key and
+/// handshake timeout.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{HttpServer, Request, Response, TlsConfig};
+/// use tokio::net::TcpListener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").await.unwrap();
+///
+///     HttpServer::from_fn(|server| server.set_tls(tls))
+///         .run(TcpListener::bind("127.0.0.1:443").await.unwrap(), work)
+///         .await;
+/// }
+///
+/// async fn work(_request: Request) -> Response {
+///     Response::from_body("All Good :)")
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    config: Arc<ServerConfig>,
+    handshake_timeout: Option<Duration>,
+    resolver: Option<Arc<CertResolver>>,
+    files: Option<Arc<(PathBuf, PathBuf)>>,
+}
+
+/// Certificate served to new handshakes, replaced on reload.
+#[derive(Debug)]
+struct CertResolver {
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current().clone())
+    }
+}
+
+impl CertResolver {
+    #[inline]
+    fn current(&self) -> Arc<CertifiedKey> {
+        self.current
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    #[inline]
+    fn set(&self, cert_chain: &[u8], key: &[u8]) -> Result<(), ServerError> {
+        let certified = TlsConfig::certified_key(cert_chain, key, &self.provider)?;
+        *self.current.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(certified);
+        Ok(())
+    }
+}
+
+/// Functions for creating and configuring [TlsConfig].
+impl TlsConfig {
+    #[inline]
+    /// Creating a new instance of a [TlsConfig] from PEM data in memory.
+    /// The handshake timeout is 10 seconds.
+    ///
+    /// # Parameters
+    /// * `cert_chain` - PEM certificates, the server certificate first.
+    /// * `key` - PEM private key (PKCS#8, PKCS#1 or SEC1).
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{HttpServer, TlsConfig};
+    ///
+    /// let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    /// let tls = TlsConfig::from_pem(
+    ///     cert.cert.pem().as_bytes(),
+    ///     cert.signing_key.serialize_pem().as_bytes(),
+    /// ).unwrap();
+    ///
+    /// let server = HttpServer::from_fn(|server| server.set_tls(tls));
+    /// ```
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Self, ServerError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let resolver = Arc::new(CertResolver {
+            current: RwLock::new(Arc::new(Self::certified_key(cert_chain, key, &provider)?)),
+            provider,
+        });
+
+        Ok(TlsConfig {
+            resolver: Some(resolver.clone()),
+            ..Self::from_server_config(Self::server_config(resolver, None)?)
+        })
+    }
+
+    #[inline]
+    /// Creating a new instance of a [TlsConfig] from PEM files.
+    /// The handshake timeout is 10 seconds.
+    /// The files can be read again with [TlsConfig::reload] or [TlsConfig::watch].
+    ///
+    /// # Parameters
+    /// * `cert_chain` - Path to the PEM certificates, the server certificate first.
+    /// * `key` - Path to the PEM private key.
+    pub async fn from_pem_files<C: AsRef<Path>, K: AsRef<Path>>(
+        cert_chain: C,
+        key: K,
+    ) -> Result<Self, ServerError> {
+        let files = (
+            cert_chain.as_ref().to_path_buf(),
+            key.as_ref().to_path_buf(),
+        );
+        let (cert_chain, key) = Self::read_files(&files).await?;
+
+        Ok(TlsConfig {
+            files: Some(Arc::new(files)),
+            ..Self::from_pem(&cert_chain, &key)?
+        })
+    }
+
+    #[inline]
+    /// Creating a new instance of a [TlsConfig] from a ready `rustls` configuration.
+    /// The handshake timeout is 10 seconds.
+    ///
+    /// # Parameters
+    /// * `config` - Server configuration of [rustls].
+    pub fn from_server_config(config: Arc<ServerConfig>) -> Self {
+        TlsConfig {
+            config,
+            handshake_timeout: Some(Duration::from_secs(10)),
+            resolver: None,
+            files: None,
+        }
+    }
+
+    #[inline]
+    /// Sets how long a client has to complete the handshake, [None] disables the limit.
+    ///
+    /// # Parameters
+    /// * `handshake_timeout` - Maximum duration of the handshake.
+    pub fn set_handshake_timeout(&mut self, handshake_timeout: Option<Duration>) {
+        self.handshake_timeout = handshake_timeout;
+    }
+
+    #[inline]
+    /// Verifies client certificates against the CA bundle (mutual TLS).
+    /// The subject and the alternative names of a verified certificate are available in
+    /// [TlsInfo] of every [Request].
+    ///
+    /// # Parameters
+    /// * `ca_bundle` - PEM certificates of the trusted certificate authorities.
+    /// * `client_auth` - Whether clients without a certificate are accepted.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{ClientAuth, HttpServer, Request, TlsConfig};
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut tls = TlsConfig::from_pem_files("cert.pem", "key.pem").await.unwrap();
+    ///     let ca_bundle = std::fs::read("clients-ca.pem").unwrap();
+    ///     tls.set_client_auth(&ca_bundle, ClientAuth::Required).unwrap();
+    ///
+    ///     HttpServer::from_fn(|server| server.set_tls(tls))
+    ///         .run(TcpListener::bind("127.0.0.1:443").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(request: Request) -> String {
+    ///     let tls = request.tls.unwrap_or_default();
+    ///     tls.peer_subject.unwrap_or_default()
+    /// }
+    /// ```
+    pub fn set_client_auth(
+        &mut self,
+        ca_bundle: &[u8],
+        client_auth: ClientAuth,
+    ) -> Result<(), ServerError> {
+        let invalid = |err: rustls::Error| ServerError::InvalidCertificate(err.to_string());
+        let resolver = self.resolver()?.clone();
+
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_slice_iter(ca_bundle) {
+            let cert = cert.map_err(|err| ServerError::InvalidCertificate(err.to_string()))?;
+            roots.add(cert).map_err(invalid)?;
+        }
+
+        let builder =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), resolver.provider.clone());
+        let builder = match client_auth {
+            ClientAuth::Optional => builder.allow_unauthenticated(),
+            ClientAuth::Required => builder,
+        };
+        let verifier = builder
+            .build()
+            .map_err(|err| ServerError::InvalidCertificate(err.to_string()))?;
+
+        self.config = Self::server_config(resolver, Some(verifier))?;
+        Ok(())
+    }
+
+    #[inline]
+    /// Verifies client certificates against the CA bundle file (mutual TLS).
+    ///
+    /// # Parameters
+    /// * `ca_bundle` - Path to the PEM certificates of the trusted certificate authorities.
+    /// * `client_auth` - Whether clients without a certificate are accepted.
+    pub async fn set_client_auth_file<P: AsRef<Path>>(
+        &mut self,
+        ca_bundle: P,
+        client_auth: ClientAuth,
+    ) -> Result<(), ServerError> {
+        let ca_bundle = fs::read(ca_bundle)
+            .await
+            .map_err(ServerError::OpeningFile)?;
+        self.set_client_auth(&ca_bundle, client_auth)
+    }
+
+    #[inline]
+    fn server_config(
+        resolver: Arc<CertResolver>,
+        verifier: Option<Arc<dyn ClientCertVerifier>>,
+    ) -> Result<Arc<ServerConfig>, ServerError> {
+        let builder = ServerConfig::builder_with_provider(resolver.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| ServerError::InvalidCertificate(err.to_string()))?;
+
+        let config = match verifier {
+            Some(verifier) => builder.with_client_cert_verifier(verifier),
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(Arc::new(config.with_cert_resolver(resolver)))
+    }
+
+    #[inline]
+    /// Parses the certificate chain and the private key and checks that they match.
+    fn certified_key(
+        cert_chain: &[u8],
+        key: &[u8],
+        provider: &CryptoProvider,
+    ) -> Result<CertifiedKey, ServerError> {
+        let invalid = |err: pem::Error| ServerError::InvalidCertificate(err.to_string());
+
+        let cert_chain = CertificateDer::pem_slice_iter(cert_chain)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        if cert_chain.is_empty() {
+            return Err(ServerError::InvalidCertificate(
+                "no certificate found".to_string(),
+            ));
+        }
+
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid)?;
+
+        CertifiedKey::from_der(cert_chain, key, provider)
+            .map_err(|err| ServerError::InvalidCertificate(err.to_string()))
+    }
+
+    #[inline]
+    async fn read_files(files: &(PathBuf, PathBuf)) -> Result<(Vec<u8>, Vec<u8>), ServerError> {
+        let cert_chain = fs::read(&files.0).await.map_err(ServerError::OpeningFile)?;
+        let key = fs::read(&files.1).await.map_err(ServerError::OpeningFile)?;
+
+        Ok((cert_chain, key))
+    }
+}
+
+/// Functions for replacing the certificate of [TlsConfig] while the server runs.
+///
+/// New handshakes use the new certificate, open connections are not affected.
+/// Clones of a [TlsConfig] share the certificate, so a clone kept aside can reload the
+/// certificate of a running server.
+impl TlsConfig {
+    #[inline]
+    /// Replaces the certificate with PEM data in memory.
+    /// On error the previous certificate is kept.
+    ///
+    /// # Parameters
+    /// * `cert_chain` - PEM certificates, the server certificate first.
+    /// * `key` - PEM private key.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::TlsConfig;
+    ///
+    /// let old = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    /// let new = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    ///
+    /// let tls = TlsConfig::from_pem(
+    ///     old.cert.pem().as_bytes(),
+    ///     old.signing_key.serialize_pem().as_bytes(),
+    /// ).unwrap();
+    /// let server_tls = tls.clone();
+    ///
+    /// tls.reload_pem(new.cert.pem().as_bytes(), new.signing_key.serialize_pem().as_bytes())
+    ///     .unwrap();
+    /// assert_eq!(server_tls.certificate().unwrap(), new.cert.der().as_ref());
+    ///
+    /// // The key does not match the certificate.
+    /// assert!(tls
+    ///     .reload_pem(old.cert.pem().as_bytes(), new.signing_key.serialize_pem().as_bytes())
+    ///     .is_err());
+    /// assert_eq!(server_tls.certificate().unwrap(), new.cert.der().as_ref());
+    /// ```
+    pub fn reload_pem(&self, cert_chain: &[u8], key: &[u8]) -> Result<(), ServerError> {
+        self.resolver()?.set(cert_chain, key)
+    }
+
+    #[inline]
+    /// Reads the certificate again from the files given to [TlsConfig::from_pem_files].
+    /// On error the previous certificate is kept.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::TlsConfig;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let name = format!("maker_web_tls_reload_{}", std::process::id());
+    ///     let dir = std::env::temp_dir().join(name);
+    ///     let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    ///     std::fs::create_dir_all(&dir).unwrap();
+    ///
+    ///     let write = |cert: &rcgen::CertifiedKey<rcgen::KeyPair>| {
+    ///         std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    ///         std::fs::write(&key_path, cert.signing_key.serialize_pem()).unwrap();
+    ///     };
+    ///
+    ///     let old = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    ///     write(&old);
+    ///     let tls = TlsConfig::from_pem_files(&cert_path, &key_path).await.unwrap();
+    ///
+    ///     let new = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    ///     write(&new);
+    ///     tls.reload().await.unwrap();
+    ///
+    ///     assert_eq!(tls.certificate().unwrap(), new.cert.der().as_ref());
+    ///     std::fs::remove_dir_all(&dir).unwrap();
+    /// }
+    /// ```
+    pub async fn reload(&self) -> Result<(), ServerError> {
+        let resolver = self.resolver()?;
+        let files = self.files.as_ref().ok_or_else(|| {
+            ServerError::InvalidCertificate("the certificate was not loaded from files".into())
+        })?;
+
+        let (cert_chain, key) = Self::read_files(files).await?;
+        resolver.set(&cert_chain, &key)
+    }
+
+    #[inline]
+    /// Starts a task checking the certificate files every `interval`, the certificate is
+    /// reloaded when their modification time or size changes. Failed reloads are reported
+    /// as crate messages and retried on the next change.
+    ///
+    /// # Parameters
+    /// * `interval` - Time between two checks of the files.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use maker_web::{HttpServer, Request, Response, TlsConfig};
+    /// use std::time::Duration;
+    /// use tokio::net::TcpListener;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tls = TlsConfig::from_pem_files("cert.pem", "key.pem").await.unwrap();
+    ///     tls.watch(Duration::from_secs(30));
+    ///
+    ///     HttpServer::from_fn(|server| server.set_tls(tls))
+    ///         .run(TcpListener::bind("127.0.0.1:443").await.unwrap(), work)
+    ///         .await;
+    /// }
+    ///
+    /// async fn work(_request: Request) -> Response {
+    ///     Response::from_body("All Good :)")
+    /// }
+    /// ```
+    pub fn watch(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let tls = self.clone();
+
+        tokio::spawn(async move {
+            let Some(files) = tls.files.clone() else {
+                return;
+            };
+            let mut last = Self::stamp(&files).await;
+
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let stamp = Self::stamp(&files).await;
+                if stamp == last {
+                    continue;
+                }
+                last = stamp;
+
+                match tls.reload().await {
+                    Ok(()) => log_event!(info, "SERVER | TLS | {} | RELOAD", files.0.display()),
+                    Err(err) => log_event!(
+                        warn,
+                        "SERVER | TLS | {} | RELOAD | {err}",
+                        files.0.display()
+                    ),
+                }
+            }
+        })
+    }
+
+    #[inline]
+    /// DER certificate currently served, [None] for [TlsConfig::from_server_config].
+    pub fn certificate(&self) -> Option<Bytes> {
+        let resolver = self.resolver.as_ref()?;
+        let current = resolver.current();
+
+        current
+            .end_entity_cert()
+            .ok()
+            .map(|cert| Bytes::copy_from_slice(cert))
+    }
+
+    #[inline]
+    fn resolver(&self) -> Result<&Arc<CertResolver>, ServerError> {
+        self.resolver.as_ref().ok_or_else(|| {
+            ServerError::InvalidCertificate("the certificate is not managed by TlsConfig".into())
+        })
+    }
+
+    #[inline]
+    async fn stamp(files: &(PathBuf, PathBuf)) -> [Option<(SystemTime, u64)>; 2] {
+        let stamp = |path: PathBuf| async move {
+            let meta = fs::metadata(path).await.ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        };
+
+        [stamp(files.0.clone()).await, stamp(files.1.clone()).await]
+    }
+}
+
+/// Functions for accepting connections with [TlsConfig].
+impl TlsConfig {
+    #[inline]
+    /// Performs the server handshake on the connection.
+    pub(crate) async fn accept<S>(&self, socket: S) -> Result<(TlsStream<S>, TlsInfo), ServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let accept = TlsAcceptor::from(self.config.clone()).accept(socket);
+        let stream = with_timeout(
+            self.handshake_timeout,
+            accept,
+            ServerError::HandshakeTimeout,
+        )
+        .await?
+        .map_err(ServerError::Handshake)?;
+
+        let info = TlsInfo::from_connection(stream.get_ref().1);
+        Ok((stream, info))
+    }
+
+    #[cfg(feature = "http2")]
+    #[inline]
+    /// Advertises the application protocols with ALPN, unless the configuration already does.
+    pub(crate) fn set_alpn(&mut self, protocols: &[&[u8]]) {
+        if self.config.alpn_protocols.is_empty() {
+            let mut config = ServerConfig::clone(&self.config);
+            config.alpn_protocols = protocols.iter().map(|protocol| protocol.to_vec()).collect();
+            self.config = Arc::new(config);
+        }
+    }
+}
+
+/// Whether [TlsConfig::set_client_auth] accepts clients without a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientAuth {
+    /// Clients may connect without a certificate, a presented certificate must be valid.
+    Optional,
+    /// Clients must present a valid certificate.
+    Required,
+}
+
+/// Properties of the TLS session a [Request] arrived on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// Server name requested by the client (SNI).
+    pub server_name: Option<String>,
+    /// DER certificate chain presented by the client, the client certificate first.
+    pub peer_certificates: Vec<Bytes>,
+    /// Subject of the client certificate, e.g. `CN=billing, O=Example`.
+    pub peer_subject: Option<String>,
+    /// DNS names, e-mail addresses, URIs and IP addresses of the client certificate.
+    pub peer_alt_names: Vec<String>,
+    /// Application protocol negotiated with ALPN, e.g. `h2` or `http/1.1`.
+    pub alpn_protocol: Option<String>,
+}
+
+/// Functions for reading [TlsInfo].
+impl TlsInfo {
+    #[inline]
+    fn from_connection(connection: &ServerConnection) -> Self {
+        let peer_certificates = connection.peer_certificates().unwrap_or_default();
+        let (peer_subject, peer_alt_names) = peer_certificates
+            .first()
+            .map(|cert| Self::peer_names(cert))
+            .unwrap_or_default();
+
+        TlsInfo {
+            server_name: connection.server_name().map(str::to_string),
+            peer_certificates: peer_certificates
+                .iter()
+                .map(|cert| Bytes::copy_from_slice(cert))
+                .collect(),
+            peer_subject,
+            peer_alt_names,
+            alpn_protocol: connection
+                .alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+        }
+    }
+
+    #[inline]
+    /// Subject and alternative names of a DER certificate.
+    fn peer_names(cert: &[u8]) -> (Option<String>, Vec<String>) {
+        let Ok((_, cert)) = X509Certificate::from_der(cert) else {
+            return (None, Vec::new());
+        };
+
+        let alt_names = match cert.subject_alternative_name() {
+            Ok(Some(names)) => names
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name)
+                    | GeneralName::RFC822Name(name)
+                    | GeneralName::URI(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(ip) => match ip.len() {
+                        4 => Some(IpAddr::from(<[u8; 4]>::try_from(*ip).ok()?).to_string()),
+                        16 => Some(IpAddr::from(<[u8; 16]>::try_from(*ip).ok()?).to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        (Some(cert.subject().to_string()), alt_names)
+    }
+
+    #[inline]
+    /// DER certificate presented by the client.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::TlsInfo;
+    ///
+    /// assert_eq!(TlsInfo::default().peer_certificate(), None);
+    /// ```
+    pub fn peer_certificate(&self) -> Option<&Bytes> {
+        self.peer_certificates.first()
+    }
+}
@@ -0,0 +1,110 @@
+use crate::rewind::Rewind;
+use crate::shared::Shared;
+use crate::*;
+
+/// Stream of a connection that can be taken over after the response.
+pub(crate) trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// Connection taken over from [HttpServer] after an upgrade response, see [Response::set_upgrade].
+///
+/// Bytes the client sent right after the request, which the server had already buffered,
+/// are read first, then reading continues from the connection.
+/// Reading and writing have no timeouts, they are up to the callback.
+pub struct Upgraded {
+    io: Rewind<Box<dyn Io>>,
+}
+
+/// Functions for creating [Upgraded].
+impl Upgraded {
+    #[inline]
+    /// Creating a new instance of a [Upgraded].
+    ///
+    /// # Parameters
+    /// * `buffered` - Bytes already read from `io` but not consumed by the request.
+    /// * `io` - Stream of the connection.
+    pub(crate) fn new<S: Io + 'static>(buffered: Bytes, io: S) -> Self {
+        Self {
+            io: Rewind::new(buffered, Box::new(io)),
+        }
+    }
+}
+
+impl fmt::Debug for Upgraded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgraded").finish_non_exhaustive()
+    }
+}
+
+impl AsyncRead for Upgraded {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Upgraded {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+type Callback = Box<dyn FnOnce(Upgraded) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Callback of a [Response] that takes over the connection.
+/// Clones share the callback, it runs at most once.
+#[derive(Debug, Clone)]
+pub(crate) struct OnUpgrade(Shared<Callback>);
+
+/// Functions for creating and running [OnUpgrade].
+impl OnUpgrade {
+    #[inline]
+    /// Creating a new instance of a [OnUpgrade].
+    ///
+    /// # Parameters
+    /// * `callback` - Asynchronous function receiving the connection.
+    pub(crate) fn new<F, Fut>(callback: F) -> Self
+    where
+        F: FnOnce(Upgraded) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let callback: Callback = Box::new(move |io| Box::pin(callback(io)));
+        Self(Shared::new(callback))
+    }
+
+    #[inline]
+    /// Runs the callback on the connection, if it has not run yet.
+    pub(crate) async fn run(self, io: Upgraded) {
+        if let Some(callback) = self.0.take() {
+            callback(io).await;
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use crate::*;
+
+/// GUID appended to the client key to compute `Sec-WebSocket-Accept` (RFC 6455).
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Message of a [WebSocket].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// UTF-8 text message.
+    Text(String),
+    /// Binary message.
+    Binary(Bytes),
+    /// Ping, received pings are answered with a pong automatically.
+    Ping(Bytes),
+    /// Pong.
+    Pong(Bytes),
+    /// Closing handshake, received close frames are answered automatically.
+    Close(Option<CloseFrame>),
+}
+
+/// Status code and reason of a close [Message].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CloseFrame {
+    /// Close status code, e.g. 1000 for a normal closure.
+    pub code: u16,
+    /// Human-readable reason, at most 123 bytes.
+    pub reason: String,
+}
+
+/// Message-level WebSocket connection (RFC 6455), created with [WebSocket::upgrade].
+///
+/// Fragmented messages are joined before they are returned by [WebSocket::recv],
+/// client frames have to be masked, server frames are sent unmasked.
+/// Protocol errors close the connection with the matching status code.
+///
+/// # Examples
+/// ```no_run
+/// use maker_web::{HttpServer, Message, Request, Response, ServerError, WebSocket};
+/// use tokio::net::TcpListener;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     HttpServer::launch(TcpListener::bind("127.0.0.1:80").await.unwrap(), work).await;
+/// }
+///
+/// async fn work(request: Request) -> Result<Response, ServerError> {
+///     if !WebSocket::is_upgrade(&request) {
+///         return Ok(Response::from_body("Not a WebSocket request"));
+///     }
+///
+///     WebSocket::upgrade(&request, |mut socket| async move {
+///         while let Ok(Some(message)) = socket.recv().await {
+///             if let Message::Text(text) = message {
+///                 let _ = socket.send(Message::Text(text)).await;
+///             }
+///         }
+///     })
+/// }
+/// ```
+#[derive(Debug)]
+pub struct WebSocket {
+    io: Upgraded,
+    buffer: BytesMut,
+    fragments: Option<(u8, Vec<u8>)>,
+    max_message_size: usize,
+    sent_close: bool,
+    received_close: bool,
+}
+
+/// Functions for the WebSocket handshake.
+impl WebSocket {
+    #[inline]
+    /// Whether the request asks for a WebSocket upgrade with the `Upgrade: websocket` header.
+    ///
+    /// # Parameters
+    /// * `request` - Request received by the handler.
+    pub fn is_upgrade(request: &Request) -> bool {
+        Self::has_token(request, UPGRADE, "websocket")
+    }
+
+    #[inline]
+    /// Answers the upgrade request with 101 Switching Protocols. After the response is written
+    /// the connection is passed to `on_upgrade` as a [WebSocket].
+    /// More headers, e.g. `Sec-WebSocket-Protocol`, can be added to the returned response.
+    ///
+    /// Returns [ServerError::InvalidUpgrade] (400) if the request is not an HTTP/1.1 GET with
+    /// `Connection: Upgrade`, `Upgrade: websocket`, `Sec-WebSocket-Version: 13` and a valid
+    /// `Sec-WebSocket-Key`.
+    ///
+    /// # Parameters
+    /// * `request` - Request received by the handler.
+    /// * `on_upgrade` - Asynchronous function serving the connection.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::{Message, Request, Response, ServerError, WebSocket};
+    ///
+    /// async fn work(request: Request) -> Result<Response, ServerError> {
+    ///     WebSocket::upgrade(&request, |mut socket| async move {
+    ///         while let Ok(Some(Message::Text(text))) = socket.recv().await {
+    ///             let _ = socket.send(Message::Text(format!("echo {text}"))).await;
+    ///         }
+    ///     })
+    /// }
+    /// ```
+    pub fn upgrade<F, Fut>(request: &Request, on_upgrade: F) -> Result<Response, ServerError>
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if request.method != Method::GET || request.version != Version::HTTP_11 {
+            return Err(ServerError::InvalidUpgrade("not an HTTP/1.1 GET request"));
+        }
+        if !Self::is_upgrade(request) || !Self::has_token(request, CONNECTION, "upgrade") {
+            return Err(ServerError::InvalidUpgrade("missing upgrade headers"));
+        }
+        if request
+            .headers
+            .get(SEC_WEBSOCKET_VERSION)
+            .map(|val| val.as_bytes())
+            != Some(b"13")
+        {
+            return Err(ServerError::InvalidUpgrade("unsupported WebSocket version"));
+        }
+
+        let key = request
+            .headers
+            .get(SEC_WEBSOCKET_KEY)
+            .and_then(|val| val.to_str().ok())
+            .filter(|key| STANDARD.decode(key).is_ok_and(|key| key.len() == 16))
+            .ok_or(ServerError::InvalidUpgrade("invalid Sec-WebSocket-Key"))?;
+
+        let mut response = Response::from_response(StatusCode::SWITCHING_PROTOCOLS, "");
+        response.add_header("Upgrade", "websocket");
+        response.add_header("Connection", "Upgrade");
+        response.add_header("Sec-WebSocket-Accept", Self::accept_key(key));
+        response.set_upgrade(move |io| on_upgrade(WebSocket::new(io)));
+
+        Ok(response)
+    }
+
+    #[inline]
+    /// Value of `Sec-WebSocket-Accept` for the `Sec-WebSocket-Key` of the client.
+    ///
+    /// # Parameters
+    /// * `key` - Value of the `Sec-WebSocket-Key` request header.
+    ///
+    /// # Examples
+    /// ```
+    /// use maker_web::WebSocket;
+    ///
+    /// assert_eq!(
+    ///     WebSocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+    ///     "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    /// );
+    /// ```
+    pub fn accept_key(key: &str) -> String {
+        let mut sha1 = Sha1::new();
+        sha1.update(key.trim().as_bytes());
+        sha1.update(GUID.as_bytes());
+
+        STANDARD.encode(sha1.digest().bytes())
+    }
+
+    #[inline]
+    fn has_token(request: &Request, name: HeaderName, token: &str) -> bool {
+        request
+            .headers
+            .get_all(name)
+            .iter()
+            .filter_map(|val| val.to_str().ok())
+            .flat_map(|val| val.split(','))
+            .any(|val| val.trim().eq_ignore_ascii_case(token))
+    }
+}
+
+/// Functions for configuring [WebSocket].
+impl WebSocket {
+    #[inline]
+    fn new(io: Upgraded) -> Self {
+        Self {
+            io,
+            buffer: BytesMut::with_capacity(4096),
+            fragments: None,
+            max_message_size: 16 * 1024 * 1024,
+            sent_close: false,
+            received_close: false,
+        }
+    }
+
+    #[inline]
+    /// Sets the maximum size of a received message, larger messages close the connection with
+    /// status 1009. The default is 16 MiB.
+    ///
+    /// # Parameters
+    /// * `max_message_size` - Maximum size in bytes, fragments of a message are counted together.
+    pub fn set_max_message_size(&mut self, max_message_size: usize) {
+        self.max_message_size = max_message_size;
+    }
+}
+
+/// Functions for exchanging [Message] over [WebSocket].
+impl WebSocket {
+    #[inline]
+    /// Receives the next message, pings are answered with a pong before they are returned.
+    /// Returns [None] once the closing handshake is done or the client disconnected.
+    pub async fn recv(&mut self) -> Result<Option<Message>, ServerError> {
+        if self.received_close {
+            return Ok(None);
+        }
+
+        let result = self.next_message().await;
+        if let Err(ServerError::WebSocket(code, reason)) = &result {
+            let frame = CloseFrame {
+                code: *code,
+                reason: reason.to_string(),
+            };
+            if !self.sent_close {
+                let _ = self.send(Message::Close(Some(frame))).await;
+            }
+            self.received_close = true;
+            let _ = self.io.shutdown().await;
+        }
+
+        result
+    }
+
+    #[inline]
+    /// Sends a message as a single frame.
+    /// After a close message only the closing handshake can be completed with [WebSocket::recv].
+    ///
+    /// # Parameters
+    /// * `message` - Message to send, pings, pongs and close reasons are limited to 125 bytes.
+    pub async fn send(&mut self, message: Message) -> Result<(), ServerError> {
+        if self.sent_close {
+            return Err(ServerError::WebSocket(1000, "close frame already sent"));
+        }
+
+        match message {
+            Message::Text(text) => self.write_frame(0x1, text.as_bytes()).await,
+            Message::Binary(data) => self.write_frame(0x2, &data).await,
+            Message::Ping(data) => self.write_frame(0x9, &data).await,
+            Message::Pong(data) => self.write_frame(0xA, &data).await,
+            Message::Close(frame) => {
+                let mut payload = Vec::new();
+                if let Some(frame) = frame {
+                    payload.extend_from_slice(&frame.code.to_be_bytes());
+                    payload.extend_from_slice(frame.reason.as_bytes());
+                }
+
+                self.sent_close = true;
+                self.write_frame(0x8, &payload).await
+            }
+        }
+    }
+
+    #[inline]
+    /// Starts the closing handshake and waits for the client to answer it.
+    ///
+    /// # Parameters
+    /// * `frame` - Status code and reason sent to the client.
+    pub async fn close(&mut self, frame: Option<CloseFrame>) -> Result<(), ServerError> {
+        if !self.sent_close {
+            self.send(Message::Close(frame)).await?;
+        }
+        while self.recv().await?.is_some() {}
+
+        Ok(())
+    }
+
+    #[inline]
+    async fn next_message(&mut self) -> Result<Option<Message>, ServerError> {
+        loop {
+            let Some((fin, opcode, payload)) = self.read_frame().await? else {
+                return Ok(None);
+            };
+
+            match opcode {
+                0x0 => {
+                    let Some((_, data)) = &mut self.fragments else {
+                        return Err(ServerError::WebSocket(1002, "unexpected continuation"));
+                    };
+                    if data.len() + payload.len() > self.max_message_size {
+                        return Err(ServerError::WebSocket(1009, "message too large"));
+                    }
+                    data.extend_from_slice(&payload);
+
+                    if fin {
+                        if let Some((opcode, data)) = self.fragments.take() {
+                            return Self::message(opcode, data).map(Some);
+                        }
+                    }
+                }
+                0x1 | 0x2 if self.fragments.is_some() => {
+                    return Err(ServerError::WebSocket(1002, "expected continuation"));
+                }
+                0x1 | 0x2 if fin => return Self::message(opcode, payload.to_vec()).map(Some),
+                0x1 | 0x2 => self.fragments = Some((opcode, payload.to_vec())),
+                0x8 => {
+                    let frame = match payload.len() {
+                        0 => None,
+                        1 => return Err(ServerError::WebSocket(1002, "invalid close frame")),
+                        _ => Some(CloseFrame {
+                            code: u16::from_be_bytes([payload[0], payload[1]]),
+                            reason: String::from_utf8(payload[2..].to_vec()).map_err(|_| {
+                                ServerError::WebSocket(1007, "invalid UTF-8 close reason")
+                            })?,
+                        }),
+                    };
+
+                    if !self.sent_close {
+                        let echo = frame.as_ref().map(|frame| CloseFrame {
+                            code: frame.code,
+                            reason: String::new(),
+                        });
+                        let _ = self.send(Message::Close(echo)).await;
+                    }
+                    self.received_close = true;
+                    let _ = self.io.shutdown().await;
+
+                    return Ok(Some(Message::Close(frame)));
+                }
+                0x9 => {
+                    if !self.sent_close {
+                        self.write_frame(0xA, &payload).await?;
+                    }
+                    return Ok(Some(Message::Ping(payload.freeze())));
+                }
+                0xA => return Ok(Some(Message::Pong(payload.freeze()))),
+                _ => return Err(ServerError::WebSocket(1002, "unknown opcode")),
+            }
+        }
+    }
+
+    #[inline]
+    fn message(opcode: u8, data: Vec<u8>) -> Result<Message, ServerError> {
+        match opcode {
+            0x1 => String::from_utf8(data)
+                .map(Message::Text)
+                .map_err(|_| ServerError::WebSocket(1007, "invalid UTF-8 text")),
+            _ => Ok(Message::Binary(Bytes::from(data))),
+        }
+    }
+
+    #[inline]
+    /// Reads the next frame and unmasks its payload.
+    /// Returns [None] if the client disconnected.
+    async fn read_frame(&mut self) -> Result<Option<(bool, u8, BytesMut)>, ServerError> {
+        loop {
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(Some(frame));
+            }
+
+            if self
+                .io
+                .read_buf(&mut self.buffer)
+                .await
+                .map_err(ServerError::Read)?
+                == 0
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    #[inline]
+    fn parse_frame(&mut self) -> Result<Option<(bool, u8, BytesMut)>, ServerError> {
+        let buf = &self.buffer[..];
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let (fin, opcode) = (buf[0] & 0x80 != 0, buf[0] & 0x0F);
+        if buf[0] & 0x70 != 0 {
+            return Err(ServerError::WebSocket(1002, "reserved bits set"));
+        }
+        if buf[1] & 0x80 == 0 {
+            return Err(ServerError::WebSocket(1002, "unmasked client frame"));
+        }
+
+        let (len, offset) = match buf[1] & 0x7F {
+            126 if buf.len() >= 4 => (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4),
+            127 if buf.len() >= 10 => {
+                let mut len = [0; 8];
+                len.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(len), 10)
+            }
+            126 | 127 => return Ok(None),
+            len => (u64::from(len), 2),
+        };
+
+        if opcode >= 0x8 && (!fin || len > 125) {
+            return Err(ServerError::WebSocket(1002, "invalid control frame"));
+        }
+        if len > self.max_message_size as u64 {
+            return Err(ServerError::WebSocket(1009, "message too large"));
+        }
+        let len = len as usize;
+        if let Some(missing) = (offset + 4 + len).checked_sub(buf.len()).filter(|n| *n > 0) {
+            self.buffer.reserve(missing);
+            return Ok(None);
+        }
+
+        let mask = [
+            buf[offset],
+            buf[offset + 1],
+            buf[offset + 2],
+            buf[offset + 3],
+        ];
+        let _ = self.buffer.split_to(offset + 4);
+        let mut payload = self.buffer.split_to(len);
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[index % 4];
+        }
+
+        Ok(Some((fin, opcode, payload)))
+    }
+
+    #[inline]
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), ServerError> {
+        if opcode >= 0x8 && payload.len() > 125 {
+            return Err(ServerError::WebSocket(1002, "control frame too large"));
+        }
+
+        let mut frame = BytesMut::with_capacity(payload.len() + 10);
+        frame.extend_from_slice(&[0x80 | opcode]);
+        match payload.len() {
+            len if len < 126 => frame.extend_from_slice(&[len as u8]),
+            len if len <= usize::from(u16::MAX) => {
+                frame.extend_from_slice(&[126]);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.extend_from_slice(&[127]);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+
+        self.io
+            .write_all(&frame)
+            .await
+            .map_err(ServerError::Write)?;
+        self.io.flush().await.map_err(ServerError::Flush)
+    }
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use http::StatusCode;
+use maker_web::{AccessEntry, AccessLog, LogFormat, Rotation};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+fn entry() -> AccessEntry {
+    AccessEntry {
+        socket_addr: "127.0.0.1:4000".parse().unwrap(),
+        method: None,
+        url: None,
+        version: None,
+        status: StatusCode::BAD_REQUEST,
+        size: 0,
+        referer: None,
+        user_agent: None,
+        duration: Duration::ZERO,
+        time: SystemTime::UNIX_EPOCH,
+    }
+}
+
+async fn wait_for_lines(path: &Path, lines: usize) -> bool {
+    for _ in 0..200 {
+        let data = std::fs::read_to_string(path).unwrap_or_default();
+        if data.lines().count() >= lines {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn write_does_not_wait_for_the_sink() {
+    let (writer, _reader) = tokio::io::duplex(1);
+    let log = AccessLog::new(writer, LogFormat::Common);
+
+    let writes = async {
+        for _ in 0..100 {
+            log.write(&entry()).await.unwrap();
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(1), writes)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn failed_rotation_keeps_logging_to_the_path() {
+    let dir = common::temp_dir("access_log_rotation");
+    let path = dir.join("access.log");
+    std::fs::create_dir_all(dir.join("access.log.1/blocked")).unwrap();
+
+    let rotation = Rotation::Size {
+        max_bytes: 1,
+        keep: 1,
+    };
+    let log = AccessLog::file(&path, LogFormat::Common, rotation)
+        .await
+        .unwrap();
+
+    log.write(&entry()).await.unwrap();
+    assert!(wait_for_lines(&path, 1).await);
+
+    let mut writes = 1;
+    loop {
+        writes += 1;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if log.write(&entry()).await.is_err() || writes == 100 {
+            break;
+        }
+    }
+    assert!(writes < 100);
+    assert!(wait_for_lines(&path, writes).await);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+#![allow(dead_code)]
+
+use http::{HeaderMap, Method, Version};
+use maker_web::{HttpServer, IntoResponse, Request};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// HTTP/1.1 request without headers, cookies or body.
+pub fn request(method: Method, url: &str) -> Request {
+    Request {
+        #[cfg(feature = "get_stream")]
+        socket_addr: "127.0.0.1:8080".parse().unwrap(),
+        method,
+        url: url.parse().unwrap(),
+        version: Version::HTTP_11,
+        cookies: HashMap::new(),
+        headers: HeaderMap::new(),
+        body: Vec::new(),
+        #[cfg(feature = "tls")]
+        tls: None,
+    }
+}
+
+/// Starts the server on a free local port and returns its address.
+pub async fn spawn<FutW, R>(
+    server: HttpServer,
+    work_fn: impl Fn(Request) -> FutW + Send + Sync + Copy + 'static,
+) -> SocketAddr
+where
+    FutW: Future<Output = R> + Send + Sync + 'static,
+    R: IntoResponse + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server.run(listener, work_fn));
+    addr
+}
+
+/// Sends the raw bytes on a new connection and reads until the server closes it.
+/// Panics if the connection is still open after 5 seconds.
+pub async fn exchange(addr: SocketAddr, request: &[u8]) -> Vec<u8> {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request).await.unwrap();
+
+    let mut response = Vec::new();
+    let read = stream.read_to_end(&mut response);
+    let _ = tokio::time::timeout(Duration::from_secs(5), read)
+        .await
+        .expect("the server did not close the connection");
+    response
+}
+
+/// Sends a `GET` closing the connection and returns the response as text.
+pub async fn get(addr: SocketAddr, path: &str) -> String {
+    let request = format!("GET {path} HTTP/1.1\r\nConnection: close\r\n\r\n");
+    String::from_utf8(exchange(addr, request.as_bytes()).await).unwrap()
+}
+
+/// Empty directory in the temporary directory, unique to the test process.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("maker_web_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
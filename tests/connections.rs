@@ -0,0 +1,67 @@
+mod common;
+
+use maker_web::{ConnectionLimit, HttpServer, Request, Saturation};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn limited_server(saturation: Saturation) -> SocketAddr {
+    async fn work(_request: Request) -> &'static str {
+        "All Good :)"
+    }
+
+    let server = HttpServer::from_fn(|server| {
+        server.set_connection_limit(ConnectionLimit {
+            max_connections: Some(1),
+            saturation,
+            ..ConnectionLimit::default()
+        })
+    });
+    common::spawn(server, work).await
+}
+
+/// Opens a kept-alive connection and waits for its first response, so it holds the slot.
+async fn hold(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+    let mut response = Vec::new();
+    while !response.ends_with(b"All Good :)") {
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await.unwrap();
+        assert!(read > 0);
+        response.extend_from_slice(&buf[..read]);
+    }
+    stream
+}
+
+#[tokio::test]
+async fn reject_answers_503_with_retry_after() {
+    let retry_after = Duration::from_millis(500);
+    let addr = limited_server(Saturation::Reject { retry_after }).await;
+    let _held = hold(addr).await;
+
+    let response = common::exchange(addr, b"GET / HTTP/1.1\r\n\r\n").await;
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    assert!(response.contains("\r\nRetry-After: 1\r\n"));
+}
+
+#[tokio::test]
+async fn wait_serves_once_the_slot_is_free() {
+    let addr = limited_server(Saturation::Wait).await;
+    let held = hold(addr).await;
+
+    let waiting = tokio::spawn(common::exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n",
+    ));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!waiting.is_finished());
+
+    drop(held);
+    let response = String::from_utf8(waiting.await.unwrap()).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("All Good :)"));
+}
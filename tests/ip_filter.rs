@@ -0,0 +1,29 @@
+use maker_web::Cidr;
+
+fn cidr(rule: &str) -> Cidr {
+    rule.parse().unwrap()
+}
+
+#[test]
+fn mapped_rules_match_ipv4() {
+    let all = cidr("::ffff:0:0/96");
+    assert_eq!(all, cidr("0.0.0.0/0"));
+    assert!(all.contains("203.0.113.7".parse().unwrap()));
+    assert!(all.contains("::ffff:203.0.113.7".parse().unwrap()));
+    assert!(!all.contains("2001:db8::1".parse().unwrap()));
+
+    let private = cidr("::ffff:10.0.0.0/104");
+    assert_eq!(private, cidr("10.0.0.0/8"));
+    assert!(private.contains("10.1.2.3".parse().unwrap()));
+    assert!(!private.contains("11.0.0.1".parse().unwrap()));
+
+    assert!("::ffff:10.0.0.0/129".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn short_mapped_prefixes_stay_ipv6() {
+    let rule = cidr("::ffff:0:0/80");
+    assert!(rule.contains("10.0.0.1".parse().unwrap()));
+    assert!(rule.contains("::1".parse().unwrap()));
+    assert!(!rule.contains("2001:db8::1".parse().unwrap()));
+}
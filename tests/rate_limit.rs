@@ -0,0 +1,51 @@
+use maker_web::RateLimiter;
+use std::net::IpAddr;
+use std::thread::sleep;
+use std::time::Duration;
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[test]
+fn sweep_keeps_exhausted_buckets() {
+    let mut limiter = RateLimiter::new(2, 0.01);
+    limiter.set_eviction(Duration::from_millis(10));
+
+    assert!(limiter.acquire(ip("10.0.0.1")).is_ok());
+    assert!(limiter.acquire(ip("10.0.0.1")).is_ok());
+    assert!(limiter.acquire(ip("10.0.0.1")).is_err());
+
+    sleep(Duration::from_millis(20));
+    limiter.acquire(ip("10.0.0.2")).unwrap();
+
+    assert_eq!(limiter.len(), 2);
+    assert!(limiter.acquire(ip("10.0.0.1")).is_err());
+}
+
+#[test]
+fn sweep_keeps_buckets_without_refill() {
+    let mut limiter = RateLimiter::new(1, 0.0);
+    limiter.set_eviction(Duration::from_millis(10));
+
+    assert!(limiter.acquire(ip("10.0.0.1")).is_ok());
+
+    sleep(Duration::from_millis(20));
+    limiter.acquire(ip("10.0.0.2")).unwrap();
+
+    assert_eq!(limiter.acquire(ip("10.0.0.1")), Err(Duration::MAX));
+}
+
+#[test]
+fn sweep_drops_refilled_buckets() {
+    let mut limiter = RateLimiter::new(2, 1000.0);
+    limiter.set_eviction(Duration::from_millis(10));
+
+    limiter.acquire(ip("10.0.0.1")).unwrap();
+    limiter.acquire(ip("10.0.0.1")).unwrap();
+
+    sleep(Duration::from_millis(20));
+    limiter.acquire(ip("10.0.0.2")).unwrap();
+
+    assert_eq!(limiter.len(), 1);
+}
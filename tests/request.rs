@@ -0,0 +1,287 @@
+mod common;
+
+use http::{HeaderValue, Method, StatusCode, Version};
+use maker_web::{HttpServer, Limits, Request};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn keep_alive_follows_version_and_connection() {
+    let mut request = common::request(Method::GET, "/");
+    assert!(request.keep_alive());
+
+    request
+        .headers
+        .insert("Connection", HeaderValue::from_static("close"));
+    assert!(!request.keep_alive());
+
+    request.version = Version::HTTP_10;
+    request.headers.clear();
+    assert!(!request.keep_alive());
+
+    request
+        .headers
+        .insert("Connection", HeaderValue::from_static("Keep-Alive"));
+    assert!(request.keep_alive());
+}
+
+#[test]
+fn last_event_id_reads_the_header() {
+    let mut request = common::request(Method::GET, "/events");
+    assert_eq!(request.last_event_id(), None);
+
+    request
+        .headers
+        .insert("Last-Event-ID", HeaderValue::from_static("42"));
+    assert_eq!(request.last_event_id(), Some("42"));
+}
+
+#[test]
+fn preconditions_answer_412_and_304() {
+    let mut request = common::request(Method::PUT, "/articles/1");
+    request
+        .headers
+        .insert("If-Match", HeaderValue::from_static("\"v1\""));
+
+    assert_eq!(request.evaluate_preconditions(Some("\"v1\""), None), None);
+    assert_eq!(
+        request.evaluate_preconditions(Some("\"v2\""), None),
+        Some(StatusCode::PRECONDITION_FAILED)
+    );
+
+    request.method = Method::GET;
+    request.headers.clear();
+    request.headers.insert(
+        "If-None-Match",
+        HeaderValue::from_static("\"v0\", W/\"v1\""),
+    );
+    assert_eq!(
+        request.evaluate_preconditions(Some("\"v1\""), None),
+        Some(StatusCode::NOT_MODIFIED)
+    );
+}
+
+#[test]
+fn preconditions_follow_the_rfc_order() {
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+    let before = "Sun, 06 Nov 1994 08:49:36 GMT";
+    let at = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    let mut request = common::request(Method::PUT, "/articles/1");
+    request
+        .headers
+        .insert("If-Unmodified-Since", HeaderValue::from_static(before));
+    assert_eq!(
+        request.evaluate_preconditions(None, Some(modified)),
+        Some(StatusCode::PRECONDITION_FAILED)
+    );
+
+    // If-Match replaces If-Unmodified-Since.
+    request
+        .headers
+        .insert("If-Match", HeaderValue::from_static("\"v1\""));
+    assert_eq!(
+        request.evaluate_preconditions(Some("\"v1\""), Some(modified)),
+        None
+    );
+
+    // A failed If-Unmodified-Since wins over a matching If-None-Match.
+    request.method = Method::GET;
+    request.headers.clear();
+    request
+        .headers
+        .insert("If-Unmodified-Since", HeaderValue::from_static(before));
+    request
+        .headers
+        .insert("If-None-Match", HeaderValue::from_static("\"v1\""));
+    assert_eq!(
+        request.evaluate_preconditions(Some("\"v1\""), Some(modified)),
+        Some(StatusCode::PRECONDITION_FAILED)
+    );
+
+    request.headers.clear();
+    request
+        .headers
+        .insert("If-Modified-Since", HeaderValue::from_static(at));
+    assert_eq!(
+        request.evaluate_preconditions(None, Some(modified)),
+        Some(StatusCode::NOT_MODIFIED)
+    );
+    request
+        .headers
+        .insert("If-Modified-Since", HeaderValue::from_static(before));
+    assert_eq!(request.evaluate_preconditions(None, Some(modified)), None);
+
+    // If-None-Match replaces If-Modified-Since.
+    request
+        .headers
+        .insert("If-Modified-Since", HeaderValue::from_static(at));
+    request
+        .headers
+        .insert("If-None-Match", HeaderValue::from_static("\"v0\""));
+    assert_eq!(
+        request.evaluate_preconditions(Some("\"v1\""), Some(modified)),
+        None
+    );
+
+    // If-Modified-Since only applies to GET and HEAD.
+    request.method = Method::PUT;
+    request.headers.remove("If-None-Match");
+    assert_eq!(request.evaluate_preconditions(None, Some(modified)), None);
+}
+
+#[cfg(feature = "get_stream")]
+#[tokio::test]
+async fn socket_addr_is_the_client_address() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn work(request: Request) -> String {
+        request.socket_addr.to_string()
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let local = stream.local_addr().unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.ends_with(&local.to_string()));
+}
+
+#[tokio::test]
+async fn response_uses_the_request_version() {
+    async fn work(request: Request) -> String {
+        format!("{:?}", request.version)
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    for (request, status_line) in [
+        ("GET / HTTP/1.0\r\n\r\n", "HTTP/1.0 200 OK\r\n"),
+        (
+            "GET / HTTP/3.0\r\n\r\n",
+            "HTTP/1.1 505 HTTP Version Not Supported\r\n",
+        ),
+        (
+            "POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            "HTTP/1.0 400 Bad Request\r\n",
+        ),
+        (
+            "POST / HTTP/1.0\r\nContent-Length: 99999999\r\n\r\n",
+            "HTTP/1.0 413 Payload Too Large\r\n",
+        ),
+    ] {
+        let response = common::exchange(addr, request.as_bytes()).await;
+        assert!(String::from_utf8_lossy(&response).starts_with(status_line));
+    }
+}
+
+#[tokio::test]
+async fn chunk_extensions_do_not_count_against_the_request_line() {
+    async fn work(request: Request) -> Vec<u8> {
+        request.body
+    }
+
+    let server = HttpServer::from_fn(|server| {
+        server.set_limits(Limits {
+            request_line: 64,
+            ..Limits::default()
+        })
+    });
+    let addr = common::spawn(server, work).await;
+
+    let request = format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+        5;ext={}\r\nhello\r\n0\r\n\r\n",
+        "a".repeat(200)
+    );
+    let response = common::exchange(addr, request.as_bytes()).await;
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello"));
+
+    let request = format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext={}\r\nhello\r\n0\r\n\r\n",
+        "a".repeat(2000)
+    );
+    let response = common::exchange(addr, request.as_bytes()).await;
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+async fn limited_server() -> std::net::SocketAddr {
+    async fn work(request: Request) -> Vec<u8> {
+        request.body
+    }
+
+    let server = HttpServer::from_fn(|server| {
+        server.set_limits(Limits {
+            request_line: 64,
+            header_bytes: 64,
+            header_count: 2,
+            body: 10,
+            ..Limits::default()
+        })
+    });
+    common::spawn(server, work).await
+}
+
+async fn status_line(addr: std::net::SocketAddr, request: &str) -> String {
+    let response = common::exchange(addr, request.as_bytes()).await;
+    let response = String::from_utf8_lossy(&response);
+    response.split("\r\n").next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn long_request_line_answers_414() {
+    let addr = limited_server().await;
+    let request = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(100));
+    assert_eq!(
+        status_line(addr, &request).await,
+        "HTTP/1.1 414 URI Too Long"
+    );
+}
+
+#[tokio::test]
+async fn too_many_headers_answer_431() {
+    let addr = limited_server().await;
+    let request = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+    assert_eq!(
+        status_line(addr, request).await,
+        "HTTP/1.1 431 Request Header Fields Too Large"
+    );
+}
+
+#[tokio::test]
+async fn large_headers_answer_431() {
+    let addr = limited_server().await;
+    let request = format!("GET / HTTP/1.1\r\nA: {}\r\n\r\n", "a".repeat(100));
+    assert_eq!(
+        status_line(addr, &request).await,
+        "HTTP/1.1 431 Request Header Fields Too Large"
+    );
+}
+
+#[tokio::test]
+async fn large_content_length_answers_413() {
+    let addr = limited_server().await;
+    let request = "POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world";
+    assert_eq!(
+        status_line(addr, request).await,
+        "HTTP/1.1 413 Payload Too Large"
+    );
+}
+
+#[tokio::test]
+async fn large_chunked_body_answers_413() {
+    let addr = limited_server().await;
+    let request = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+        6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n";
+    assert_eq!(
+        status_line(addr, request).await,
+        "HTTP/1.1 413 Payload Too Large"
+    );
+}
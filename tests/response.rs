@@ -0,0 +1,209 @@
+mod common;
+
+use http::{Method, StatusCode};
+use maker_web::{CleanServer, Event, HttpServer, Request, Response};
+use once_cell::sync::Lazy;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+#[tokio::test]
+async fn events_are_streamed_as_chunks() {
+    async fn work(request: Request) -> Response {
+        let last_id = request
+            .last_event_id()
+            .and_then(|id| id.parse::<u32>().ok());
+        let (response, sender) = Response::from_events(Some(Duration::from_secs(15)));
+
+        tokio::spawn(async move {
+            let id = last_id.unwrap_or(0) + 1;
+            let event = Event::from_fn(|event| {
+                event.id = Some(id.to_string());
+                event.data = Some(id.to_string());
+            });
+            let _ = sender.send(event).await;
+        });
+        response
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+    let request = b"GET /events HTTP/1.1\r\nLast-Event-ID: 1\r\nConnection: close\r\n\r\n";
+    let response = String::from_utf8(common::exchange(addr, request).await).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n"));
+    assert!(response.ends_with("\r\n\r\nf\r\ndata: 2\nid: 2\n\n\r\n0\r\n\r\n"));
+}
+
+static FILE: Lazy<PathBuf> = Lazy::new(|| common::temp_dir("set_file").join("hello.txt"));
+
+#[tokio::test]
+async fn file_ranges_are_served_with_206() {
+    async fn work(_: Request) -> Response {
+        Response::from_file(&*FILE, "text/plain").await.unwrap()
+    }
+
+    std::fs::write(&*FILE, "Hello, world!").unwrap();
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    let request = b"GET / HTTP/1.1\r\nRange: bytes=7-11\r\nConnection: close\r\n\r\n";
+    let response = String::from_utf8(common::exchange(addr, request).await).unwrap();
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("Content-Range: bytes 7-11/13\r\n"));
+    assert!(response.ends_with("\r\n\r\nworld"));
+
+    let response = common::get(addr, "/").await;
+    assert!(response.contains("Content-Length: 13\r\n"));
+    assert!(response.ends_with("\r\n\r\nHello, world!"));
+
+    std::fs::remove_dir_all(FILE.parent().unwrap()).unwrap();
+}
+
+static RANGES: Lazy<PathBuf> = Lazy::new(|| common::temp_dir("ranges"));
+
+/// Serves `Hello, world!` from a file created on the first call with the name,
+/// and sends a `GET` for it with the extra header lines.
+async fn get_range(name: &str, headers: &str) -> String {
+    async fn work(request: Request) -> Response {
+        let path = RANGES.join(&request.url.path()[1..]);
+        Response::from_file(path, "text/plain").await.unwrap()
+    }
+
+    let path = RANGES.join(name);
+    if !path.exists() {
+        std::fs::write(path, "Hello, world!").unwrap();
+    }
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    let request = format!("GET /{name} HTTP/1.1\r\n{headers}Connection: close\r\n\r\n");
+    let response = common::exchange(addr, request.as_bytes()).await;
+    String::from_utf8(response).unwrap()
+}
+
+/// Value of a header of the response head.
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    let head = response.split("\r\n\r\n").next().unwrap();
+    head.split("\r\n")
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+}
+
+#[tokio::test]
+async fn overlapping_and_adjacent_ranges_are_merged() {
+    let response = get_range("merged.txt", "Range: bytes=0-2,3-4,1-3\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert_eq!(header(&response, "Content-Range"), Some("bytes 0-4/13"));
+    assert!(response.ends_with("\r\n\r\nHello"));
+
+    let response = get_range("suffix.txt", "Range: bytes=-3,7-\r\n").await;
+    assert_eq!(header(&response, "Content-Range"), Some("bytes 7-12/13"));
+    assert!(response.ends_with("\r\n\r\nworld!"));
+}
+
+#[tokio::test]
+async fn unsatisfiable_ranges_answer_416() {
+    let response = get_range("unsatisfiable.txt", "Range: bytes=13-20,20-\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert_eq!(header(&response, "Content-Range"), Some("bytes */13"));
+    assert_eq!(header(&response, "Content-Type"), None);
+}
+
+#[tokio::test]
+async fn several_ranges_are_sent_as_multipart() {
+    let response = get_range("multipart.txt", "Range: bytes=7-11,0-4\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+
+    let content_type = header(&response, "Content-Type").unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+    let body = format!(
+        "\r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-4/13\r\n\r\nHello\
+        \r\n--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 7-11/13\r\n\r\nworld\
+        \r\n--{boundary}--\r\n"
+    );
+    assert!(response.ends_with(&format!("\r\n\r\n{body}")));
+}
+
+#[tokio::test]
+async fn if_range_falls_back_to_the_whole_file() {
+    let response = get_range("if_range.txt", "").await;
+    let etag = header(&response, "ETag").unwrap().to_string();
+    let last_modified = header(&response, "Last-Modified").unwrap().to_string();
+
+    let matching = format!("Range: bytes=0-4\r\nIf-Range: {etag}\r\n");
+    let response = get_range("if_range.txt", &matching).await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.ends_with("\r\n\r\nHello"));
+
+    for if_range in [
+        "\"other\"".to_string(),
+        format!("W/{etag}"),
+        "Sun, 06 Nov 1994 08:49:37 GMT".to_string(),
+    ] {
+        let headers = format!("Range: bytes=0-4\r\nIf-Range: {if_range}\r\n");
+        let response = get_range("if_range.txt", &headers).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(header(&response, "Content-Range"), None);
+        assert!(response.ends_with("\r\n\r\nHello, world!"));
+    }
+
+    let by_date = format!("Range: bytes=0-4\r\nIf-Range: {last_modified}\r\n");
+    let response = get_range("if_range.txt", &by_date).await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+}
+
+#[tokio::test]
+async fn unchanged_responses_answer_304() {
+    async fn work(_: Request) -> Response {
+        let mut response = Response::from_body("Hello, world!");
+        response.set_etag("v1");
+        response.set_last_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777));
+        response
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+    let get = |headers: &'static str| async move {
+        let request = format!("GET / HTTP/1.1\r\n{headers}Connection: close\r\n\r\n");
+        String::from_utf8(common::exchange(addr, request.as_bytes()).await).unwrap()
+    };
+
+    let response = get("If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(response.contains("\r\nETag: \"v1\"\r\n"));
+    assert!(response.ends_with("\r\n\r\n"));
+
+    let response = get("If-Modified-Since: Sun, 06 Nov 1994 08:49:36 GMT\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nHello, world!"));
+
+    // The entity tag is compared instead of the date.
+    let response =
+        get("If-None-Match: \"v0\"\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    let response = get("If-Match: \"v0\"\r\n").await;
+    assert!(response.starts_with("HTTP/1.1 412 Precondition Failed\r\n"));
+}
+
+#[tokio::test]
+async fn connect_hands_over_the_connection() {
+    async fn work(request: Request) -> Response {
+        if request.method != Method::CONNECT {
+            return Response::from_response(StatusCode::METHOD_NOT_ALLOWED, "");
+        }
+
+        let mut response = Response::from_response(StatusCode::OK, "");
+        response.set_upgrade(|mut io| async move {
+            let data = CleanServer::read_bytes(&mut io).await.unwrap();
+            CleanServer::write(&mut io, [b"tunnel: ", &data[..]].concat())
+                .await
+                .unwrap();
+        });
+        response
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    // The first tunnel bytes are sent together with the request.
+    let request = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\nping";
+    let response = common::exchange(addr, request).await;
+    assert_eq!(response, b"HTTP/1.1 200 OK\r\n\r\ntunnel: ping");
+}
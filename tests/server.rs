@@ -0,0 +1,131 @@
+mod common;
+
+use maker_web::{HttpServer, Request, Timeouts};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[cfg(feature = "check_stream")]
+#[tokio::test]
+async fn failed_check_closes_without_response() {
+    async fn check(_: std::net::SocketAddr) -> bool {
+        false
+    }
+    async fn work(_request: Request) -> &'static str {
+        "All Good :)"
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(HttpServer::new().run_with_check(listener, work, check));
+
+    assert!(common::exchange(addr, b"GET / HTTP/1.1\r\n\r\n")
+        .await
+        .is_empty());
+}
+
+#[cfg(feature = "http2")]
+#[tokio::test]
+async fn http2_prior_knowledge_is_served() {
+    async fn work(request: Request) -> String {
+        let host = request.headers["host"].to_str().unwrap();
+        format!("{} | {host}", request.url)
+    }
+
+    let server = HttpServer::from_fn(|server| server.set_http2(true));
+    let addr = common::spawn(server, work).await;
+
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (client, connection) = h2::client::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+
+    let request = http::Request::get("http://localhost/hello?name=h2")
+        .body(())
+        .unwrap();
+    let (response, _) = client
+        .ready()
+        .await
+        .unwrap()
+        .send_request(request, true)
+        .unwrap();
+
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let body = response.into_body().data().await.unwrap().unwrap();
+    assert_eq!(body, "/hello?name=h2 | localhost");
+}
+
+async fn timed_server() -> std::net::SocketAddr {
+    async fn work(request: Request) -> String {
+        if request.url == "/slow" {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        if request.url == "/large" {
+            return "a".repeat(32 * 1024 * 1024);
+        }
+        String::from_utf8(request.body).unwrap()
+    }
+
+    let server = HttpServer::from_fn(|server| {
+        server.set_timeouts(Timeouts {
+            head: Some(Duration::from_millis(100)),
+            body: Some(Duration::from_millis(100)),
+            handler: Some(Duration::from_millis(100)),
+            write: Some(Duration::from_millis(100)),
+            keep_alive: Some(Duration::from_millis(100)),
+        })
+    });
+    common::spawn(server, work).await
+}
+
+#[tokio::test]
+async fn partial_head_answers_408() {
+    let addr = timed_server().await;
+    let response = common::exchange(addr, b"GET / HTTP/1.1\r\nHost: localhost\r\n").await;
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+}
+
+#[tokio::test]
+async fn partial_body_answers_408() {
+    let addr = timed_server().await;
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello";
+    let response = common::exchange(addr, request).await;
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+}
+
+#[tokio::test]
+async fn slow_handler_answers_503() {
+    let addr = timed_server().await;
+    let response = common::exchange(addr, b"GET /slow HTTP/1.1\r\n\r\n").await;
+    assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+}
+
+#[tokio::test]
+async fn unread_response_is_dropped_after_the_write_timeout() {
+    let addr = timed_server().await;
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /large HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut response = Vec::new();
+    let read = stream.read_to_end(&mut response);
+    let _ = tokio::time::timeout(Duration::from_secs(5), read)
+        .await
+        .expect("the server did not close the connection");
+    assert!(response.len() < 32 * 1024 * 1024);
+}
+
+#[tokio::test]
+async fn idle_connection_is_closed_after_keep_alive() {
+    let addr = timed_server().await;
+    let start = Instant::now();
+    let response = common::exchange(addr, b"GET / HTTP/1.1\r\n\r\n").await;
+
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(response.matches("HTTP/1.1").count(), 1);
+}
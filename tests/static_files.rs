@@ -0,0 +1,62 @@
+mod common;
+
+use maker_web::{HttpServer, Request, Response, ServerError, StaticFiles};
+use once_cell::sync::Lazy;
+use std::path::PathBuf;
+
+static ROOT: Lazy<PathBuf> = Lazy::new(|| common::temp_dir("static_files"));
+static FILES: Lazy<StaticFiles> = Lazy::new(|| StaticFiles::new("/static", &*ROOT));
+static REDIRECT_ROOT: Lazy<PathBuf> = Lazy::new(|| common::temp_dir("static_redirect"));
+static REDIRECT_FILES: Lazy<StaticFiles> = Lazy::new(|| StaticFiles::new("/", &*REDIRECT_ROOT));
+
+#[tokio::test]
+async fn files_under_the_prefix_are_served() {
+    async fn work(request: Request) -> Result<Response, ServerError> {
+        if let Some(response) = FILES.serve(&request).await {
+            return response;
+        }
+
+        Ok(Response::from_body("Application"))
+    }
+
+    std::fs::write(ROOT.join("hello.txt"), "Hello").unwrap();
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    let hello = common::get(addr, "/static/hello.txt").await;
+    assert!(hello.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(hello.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+    assert!(hello.ends_with("\r\n\r\nHello"));
+
+    let missing = common::get(addr, "/static/missing.txt").await;
+    assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    let escape = common::get(addr, "/static/%2e%2e/secret").await;
+    assert!(escape.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+    assert!(common::get(addr, "/api/users")
+        .await
+        .ends_with("\r\n\r\nApplication"));
+
+    std::fs::remove_dir_all(&*ROOT).unwrap();
+}
+
+#[tokio::test]
+async fn directory_redirects_stay_on_the_server() {
+    async fn work(request: Request) -> Result<Response, ServerError> {
+        REDIRECT_FILES
+            .serve(&request)
+            .await
+            .unwrap_or_else(|| Ok(Response::new()))
+    }
+
+    std::fs::create_dir_all(REDIRECT_ROOT.join("docs")).unwrap();
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    let docs = common::get(addr, "/docs?page=2").await;
+    assert!(docs.contains("\r\nLocation: /docs/?page=2\r\n"));
+    assert!(common::get(addr, "//evil.com/..")
+        .await
+        .contains("\r\nLocation: /\r\n"));
+    let evil = common::get(addr, "//evil.com/../docs").await;
+    assert!(evil.contains("\r\nLocation: /docs/\r\n"));
+
+    std::fs::remove_dir_all(&*REDIRECT_ROOT).unwrap();
+}
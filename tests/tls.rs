@@ -0,0 +1,100 @@
+#![cfg(feature = "tls")]
+
+mod common;
+
+use maker_web::{rustls, ClientAuth, HttpServer, Request, TlsConfig};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName};
+use std::{convert::TryFrom, net::SocketAddr, sync::Arc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+fn server_tls() -> (TlsConfig, CertificateDer<'static>) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let tls = TlsConfig::from_pem(
+        cert.cert.pem().as_bytes(),
+        cert.signing_key.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+    (tls, cert.cert.der().clone())
+}
+
+async fn get(addr: SocketAddr, client: rustls::ClientConfig) -> String {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut stream = TlsConnector::from(Arc::new(client))
+        .connect(name, stream)
+        .await
+        .unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response).await;
+    String::from_utf8(response).unwrap()
+}
+
+fn client_config(
+    server_cert: CertificateDer<'static>,
+) -> rustls::ConfigBuilder<rustls::ClientConfig, rustls::client::WantsClientCert> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(server_cert).unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+}
+
+#[tokio::test]
+async fn handshake_exposes_the_server_name() {
+    async fn work(request: Request) -> String {
+        request
+            .tls
+            .and_then(|tls| tls.server_name)
+            .unwrap_or_default()
+    }
+
+    let (tls, cert) = server_tls();
+    let addr = common::spawn(HttpServer::from_fn(|server| server.set_tls(tls)), work).await;
+
+    let response = get(addr, client_config(cert).with_no_client_auth()).await;
+    assert!(response.ends_with("\r\n\r\nlocalhost"));
+}
+
+#[tokio::test]
+async fn client_certificates_are_verified() {
+    async fn work(request: Request) -> String {
+        let tls = request.tls.unwrap_or_default();
+        let subject = tls.peer_subject.unwrap_or_default();
+        format!("{subject} | {}", tls.peer_alt_names.join(","))
+    }
+
+    let mut ca = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(ca, KeyPair::generate().unwrap()).unwrap();
+
+    let mut client = CertificateParams::new(vec!["billing.internal".into()]).unwrap();
+    client
+        .distinguished_name
+        .push(DnType::CommonName, "billing");
+    let client_key = KeyPair::generate().unwrap();
+    let client_cert = client.signed_by(&client_key, &ca).unwrap();
+
+    let (mut tls, cert) = server_tls();
+    tls.set_client_auth(ca.pem().as_bytes(), ClientAuth::Required)
+        .unwrap();
+    let addr = common::spawn(HttpServer::from_fn(|server| server.set_tls(tls)), work).await;
+
+    let client = client_config(cert)
+        .with_client_auth_cert(
+            vec![client_cert.der().clone()],
+            PrivatePkcs8KeyDer::from(client_key.serialize_der()).into(),
+        )
+        .unwrap();
+    let response = get(addr, client).await;
+    assert!(response.ends_with("\r\n\r\nCN=billing | billing.internal"));
+}
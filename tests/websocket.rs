@@ -0,0 +1,49 @@
+#![cfg(feature = "websocket")]
+
+mod common;
+
+use maker_web::{HttpServer, Message, Request, Response, ServerError, WebSocket};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn upgraded_connection_echoes_messages() {
+    async fn work(request: Request) -> Result<Response, ServerError> {
+        WebSocket::upgrade(&request, |mut socket| async move {
+            while let Ok(Some(Message::Text(text))) = socket.recv().await {
+                let _ = socket.send(Message::Text(format!("echo {text}"))).await;
+            }
+        })
+    }
+
+    let addr = common::spawn(HttpServer::new(), work).await;
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(
+            b"GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\n\
+            Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut head = [0; 129];
+    stream.read_exact(&mut head).await.unwrap();
+    assert_eq!(
+        &head[..],
+        b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+        Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+    );
+
+    // A masked text frame with "hi".
+    let mask = [1, 2, 3, 4];
+    stream
+        .write_all(&[0x81, 0x82, 1, 2, 3, 4, b'h' ^ mask[0], b'i' ^ mask[1]])
+        .await
+        .unwrap();
+
+    let mut echo = [0; 9];
+    stream.read_exact(&mut echo).await.unwrap();
+    assert_eq!(&echo, b"\x81\x07echo hi");
+}